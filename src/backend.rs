@@ -0,0 +1,83 @@
+use crate::runner::Runner;
+use crate::{Monitors, XrandrCommand};
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A display tool that lists the outputs and applies plans to them.
+pub trait Backend {
+    fn query(&mut self) -> Result<Monitors>;
+    fn apply(&mut self, plan: &XrandrCommand) -> Result<()>;
+    /// The program and arguments `apply` would run for `plan`.
+    fn command_line(&self, plan: &XrandrCommand) -> Result<Vec<String>>;
+}
+
+/// xrandr through a runner, the same calls the X11 path makes.
+pub struct XrandrBackend<R: Runner>(pub R);
+
+impl<R: Runner> Backend for XrandrBackend<R> {
+    fn query(&mut self) -> Result<Monitors> {
+        let output = self.0.xrandr(&["-q".to_string()])?;
+        Ok(Monitors::from_query(output.trim())?)
+    }
+    fn apply(&mut self, plan: &XrandrCommand) -> Result<()> {
+        self.0.xrandr(&plan.command_string())?;
+        Ok(())
+    }
+    fn command_line(&self, plan: &XrandrCommand) -> Result<Vec<String>> {
+        Ok([vec!["xrandr".to_string()], plan.command_string()].concat())
+    }
+}
+
+/// `wlr-randr`, for Sway and other wlroots compositors.
+pub struct WlrRandrBackend;
+
+impl WlrRandrBackend {
+    fn wlr_randr(&self, args: &[String]) -> Result<String> {
+        let output = Command::new("wlr-randr")
+            .args(args)
+            .output()
+            .context("During launching wlr-randr an error occured")?;
+        if !output.status.success() {
+            bail!(
+                "wlr-randr {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout)
+            .context("Non utf8 characters encountered when parsing wlr-randr output.")
+    }
+}
+
+impl Backend for WlrRandrBackend {
+    fn query(&mut self) -> Result<Monitors> {
+        Ok(Monitors::from_wlr_text(&self.wlr_randr(&[])?)?)
+    }
+    fn apply(&mut self, plan: &XrandrCommand) -> Result<()> {
+        self.wlr_randr(&plan.wlr_command_string()?)?;
+        Ok(())
+    }
+    fn command_line(&self, plan: &XrandrCommand) -> Result<Vec<String>> {
+        Ok([vec!["wlr-randr".to_string()], plan.wlr_command_string()?].concat())
+    }
+}
+
+/// Whether to drive wlr-randr rather than xrandr. `NOT_SHIT_RANDR_BACKEND` picks one by name,
+/// otherwise wlr-randr is used under Wayland when it is on the `PATH`.
+pub fn use_wlr_randr() -> Result<bool> {
+    match std::env::var("NOT_SHIT_RANDR_BACKEND").ok().as_deref() {
+        Some("xrandr") => Ok(false),
+        Some("wlr-randr") => Ok(true),
+        Some(backend) => bail!(
+            "Expected NOT_SHIT_RANDR_BACKEND to be xrandr or wlr-randr: {}",
+            backend
+        ),
+        None => Ok(std::env::var_os("WAYLAND_DISPLAY").is_some()
+            && std::env::var_os("PATH").is_some_and(|path| on_path(&path, "wlr-randr"))),
+    }
+}
+
+fn on_path(path: &std::ffi::OsStr, program: &str) -> bool {
+    std::env::split_paths(path).any(|dir| Path::new(&dir).join(program).is_file())
+}
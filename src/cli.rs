@@ -0,0 +1,323 @@
+use anyhow::{bail, Context, Result};
+use not_shit_randr::monitors::{Arrangement, Strategy};
+use not_shit_randr::plan::{parse_mode, ApplyOrder, ModeRequest, Panning, Rotation};
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Command line options, parsed by hand.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Monitors whose max mode has fewer pixels than this are never selected.
+    pub min_area: usize,
+    /// Overrides the default config location. `--watch` reloads it when it changes.
+    pub config: Option<PathBuf>,
+    /// Print the layout key for the connected monitors and exit.
+    pub print_connected_signature: bool,
+    /// Connected outputs to keep on, every other output is turned off.
+    pub keep_only: Vec<String>,
+    /// Set every connected output to this mode, for resetting to something known safe.
+    pub force_mode_on_all: Option<(usize, usize)>,
+    /// Command run after applying the layout, the previous layout is restored once it exits.
+    pub restore_on_exit: Vec<String>,
+    /// Fail on anything in the xrandr output that would otherwise be skipped with a warning.
+    pub strict: bool,
+    /// Pick e.g. 59.94 over 60 when a mode offers both.
+    pub prefer_fractional: bool,
+    /// Run every output at its mode with the highest refresh rate, even a smaller one.
+    pub prefer_higher_refresh_over_resolution: bool,
+    /// Outputs to make primary in order of preference, before falling back to the largest.
+    pub primary_chain: Vec<String>,
+    /// Mirror the primary output onto every other connected output.
+    pub clone_primary_to_all: bool,
+    /// Never pass `--primary`, for multi-seat and kiosk setups.
+    pub no_primary: bool,
+    /// File or command reporting the orientation to rotate laptop panels to.
+    pub orientation_source: Option<String>,
+    /// Print the DPI of each connected monitor and exit.
+    pub ppi_report: bool,
+    /// Named layout from the config to apply.
+    pub profile: Option<String>,
+    /// Drop the rates saved in the profile, and the highest rate otherwise picked, leaving
+    /// xrandr to choose the refresh rate live.
+    pub strip_profile_rates: bool,
+    /// Per output `NAME=WxH`, `NAME=preferred` or `NAME=max` modes, these win over profiles
+    /// and the automatic selection.
+    pub modes: Vec<(String, ModeRequest)>,
+    /// Per output `NAME=HZ` refresh rates, which the output's mode in the layout has to offer.
+    pub rates: Vec<(String, f32)>,
+    /// How long to wait for xrandr's two views of the outputs to agree.
+    pub settle_timeout: Option<Duration>,
+    /// Only consider outputs driven by this provider from `xrandr --listproviders`.
+    pub provider: Option<String>,
+    /// Print the active outputs as one line of JSON and exit.
+    pub summary_json: bool,
+    /// Step the primary output to its next mode, remembering the position between runs.
+    pub cycle_resolution: bool,
+    /// Whether enabled or disabled outputs come first in the command, `on-first` by default.
+    pub apply_order: ApplyOrder,
+    /// Largest mode any output may be set to, bigger outputs get their best mode within it.
+    pub max_resolution: Option<(usize, usize)>,
+    /// Vertical resolution, as in 1080p, to run each enabled output at its widest mode of.
+    pub height: Option<usize>,
+    /// Resolution to get as close to as each enabled output's modes allow.
+    pub target_resolution: Option<(usize, usize)>,
+    /// `WxH` the automatically selected primary has to offer, the largest output that does wins.
+    pub primary_must_support: Option<(usize, usize)>,
+    /// Unix socket to read newline delimited JSON plans from, needs the `listen` feature.
+    pub listen: Option<PathBuf>,
+    /// Per output `NAME=WxH+X+Y` panning areas.
+    pub panning: Vec<(String, Panning)>,
+    /// Per output `NAME=normal`, `NAME=left`, `NAME=right` or `NAME=inverted` rotations.
+    pub rotations: Vec<(String, Rotation)>,
+    /// Factor to scale the selected output by, e.g. 1.5 for 1.5x1.5.
+    pub scale: Option<f32>,
+    /// Per output `NAME=PROPERTY=VALUE` output properties to `--set`, e.g. `Broadcast RGB`.
+    pub properties: Vec<(String, (String, String))>,
+    /// Connector prefixes of laptop panels, overriding the config.
+    pub internal_prefixes: Option<Vec<String>>,
+    /// Print the xrandr command that would run as one shell quoted line instead of running it,
+    /// also spelled `--dry-run`.
+    pub print_command: bool,
+    /// Print every connected output's modes and exit.
+    pub list_modes: bool,
+    /// Merge repeated resolutions and near identical rates in `--list-modes`.
+    pub dedupe_modes: bool,
+    /// Only pick the largest among the monitors with a matching name.
+    pub select_by_name_regex: Option<Regex>,
+    /// Put back the layout found at start up when stopped with SIGTERM or SIGINT.
+    pub restore_on_term: bool,
+    /// Print every connected monitor with its mode and exit, marking the one that would be
+    /// selected.
+    pub list: bool,
+    /// Limit `--list` and `--list-modes` to outputs driving pixels right now.
+    pub active_only: bool,
+    /// Rotate portrait panels as their EDID describes them.
+    pub auto_rotate_from_edid: bool,
+    /// `--summary-json` output to compare the active outputs against, exiting 1 on any difference.
+    pub compare_current_json: Option<PathBuf>,
+    /// Which monitor the automatic selection picks, `largest` by default.
+    pub strategy: Strategy,
+    /// Whether the monitors not selected are turned off, extended onto or mirror the selected
+    /// one, `single` by default.
+    pub arrangement: Arrangement,
+    /// Keep the current primary when it is as big as the monitor the automatic selection picks.
+    pub sticky_primary: bool,
+    /// Exit 0 when xrandr reports at least one connected monitor and 1 otherwise, printing nothing.
+    pub health_check: bool,
+    /// Turn outputs on with xrandr's `--auto` rather than naming a mode.
+    pub auto_mode: bool,
+    /// arandr or autorandr `.screenlayout` script whose xrandr call to run instead of choosing.
+    pub run_screenlayout: Option<PathBuf>,
+    /// Query the active outputs after applying and fail unless they match the plan.
+    pub verify: bool,
+    /// `OUTPUT=N` to divide an active output into N side by side virtual monitors and exit.
+    pub split: Option<(String, usize)>,
+    /// Apply the layout even when it is already in place, `--watch` always does on its first run.
+    pub force_first: bool,
+    /// Print the raw `xrandr -q` output with the model parsed from it and exit, for bug reports.
+    pub debug_model: bool,
+    /// Print a monitoring plugin status line and exit 0, 1 or 2 for OK, WARNING or CRITICAL.
+    pub nagios: bool,
+    /// Print the connected monitors and the one that would be selected as JSON and exit.
+    pub json: bool,
+    /// Let outputs xrandr reports as `unknown connection` be selected.
+    pub include_unknown: bool,
+    /// Shift the layout so its top left corner is at 0,0.
+    pub normalize_positions: bool,
+    /// Keep running, choosing the layout again whenever the connected outputs change.
+    pub watch: bool,
+    /// Warn when the layout enables more outputs than the GPU is known to drive at once.
+    pub max_outputs: Option<usize>,
+    /// `OUTPUT=LABEL` monitors to add with `--setmonitor` so other tools can find an output by
+    /// LABEL, then exit.
+    pub labels: Vec<(String, String)>,
+    /// Remove every virtual monitor, undoing `--split` and other `--setmonitor` setups, and exit.
+    pub delete_virtual: bool,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--min-area" => {
+                    options.min_area = value(&mut args, &arg)?
+                        .parse()
+                        .context("--min-area expects a number of pixels")?;
+                }
+                "--config" | "--layout-file" => {
+                    options.config = Some(value(&mut args, &arg)?.into())
+                }
+                "--print-connected-signature" => options.print_connected_signature = true,
+                "--keep-only" => options.keep_only = list(&value(&mut args, &arg)?),
+                "--force-mode-on-all" => {
+                    options.force_mode_on_all = Some(parse_mode(&value(&mut args, &arg)?)?);
+                }
+                "--restore-on-exit" => {
+                    if args.next().as_deref() != Some("--") {
+                        bail!("--restore-on-exit expects -- followed by a command");
+                    }
+                    options.restore_on_exit = args.by_ref().collect();
+                    if options.restore_on_exit.is_empty() {
+                        bail!("--restore-on-exit expects a command after --");
+                    }
+                }
+                "--strict" => options.strict = true,
+                "--prefer-fractional" => options.prefer_fractional = true,
+                "--prefer-higher-refresh-over-resolution" => {
+                    options.prefer_higher_refresh_over_resolution = true;
+                }
+                "--primary-chain" => options.primary_chain = list(&value(&mut args, &arg)?),
+                "--clone-primary-to-all" => options.clone_primary_to_all = true,
+                "--no-primary" => options.no_primary = true,
+                "--orientation-source" => {
+                    options.orientation_source = Some(value(&mut args, &arg)?);
+                }
+                "--ppi-report" => options.ppi_report = true,
+                "--profile" => options.profile = Some(value(&mut args, &arg)?),
+                "--strip-profile-rates" => options.strip_profile_rates = true,
+                "--mode" => {
+                    let (name, mode) = assignment(&value(&mut args, &arg)?)?;
+                    options.modes.push((name, mode.parse()?));
+                }
+                "--rate" => {
+                    let (name, rate) = assignment(&value(&mut args, &arg)?)?;
+                    let hz: f32 = rate
+                        .parse()
+                        .context(format!("--rate expects a number of Hz: {}", rate))?;
+                    if !hz.is_finite() || hz <= 0.0 {
+                        bail!("--rate expects a positive number of Hz: {}", rate);
+                    }
+                    options.rates.push((name, hz));
+                }
+                "--settle-timeout" => {
+                    let millis = value(&mut args, &arg)?;
+                    let millis = millis.parse().context(format!(
+                        "--settle-timeout should be milliseconds: {}",
+                        millis
+                    ))?;
+                    options.settle_timeout = Some(Duration::from_millis(millis));
+                }
+                "--provider" => options.provider = Some(value(&mut args, &arg)?),
+                "--summary-json" => options.summary_json = true,
+                "--cycle-resolution" => options.cycle_resolution = true,
+                "--apply-order" => options.apply_order = value(&mut args, &arg)?.parse()?,
+                "--max-resolution" => {
+                    options.max_resolution = Some(parse_mode(&value(&mut args, &arg)?)?);
+                }
+                "--height" => {
+                    options.height = Some(
+                        value(&mut args, &arg)?
+                            .parse()
+                            .context("--height expects a number of pixels")?,
+                    );
+                }
+                "--target-resolution" => {
+                    options.target_resolution = Some(parse_mode(&value(&mut args, &arg)?)?);
+                }
+                "--primary-must-support" => {
+                    options.primary_must_support = Some(parse_mode(&value(&mut args, &arg)?)?);
+                }
+                "--listen" => options.listen = Some(value(&mut args, &arg)?.into()),
+                "--split" => {
+                    let (name, parts) = assignment(&value(&mut args, &arg)?)?;
+                    let parts = parts
+                        .parse()
+                        .context(format!("--split expects a number of parts: {}", parts))?;
+                    options.split = Some((name, parts));
+                }
+                "--delete-virtual" => options.delete_virtual = true,
+                "--label" => options.labels.push(assignment(&value(&mut args, &arg)?)?),
+                "--panning" => {
+                    let (name, panning) = assignment(&value(&mut args, &arg)?)?;
+                    options.panning.push((name, panning.parse()?));
+                }
+                "--rotate" => {
+                    let (name, rotation) = assignment(&value(&mut args, &arg)?)?;
+                    options.rotations.push((name, rotation.parse()?));
+                }
+                "--scale" => {
+                    let scale = value(&mut args, &arg)?;
+                    let factor: f32 = scale
+                        .parse()
+                        .context(format!("--scale expects a number: {}", scale))?;
+                    if !factor.is_finite() || factor <= 0.0 {
+                        bail!("--scale expects a positive factor: {}", scale);
+                    }
+                    options.scale = Some(factor);
+                }
+                "--set" => {
+                    let (name, property) = assignment(&value(&mut args, &arg)?)?;
+                    options.properties.push((name, assignment(&property)?));
+                }
+                "--internal-prefixes" => {
+                    options.internal_prefixes = Some(list(&value(&mut args, &arg)?));
+                }
+                "--print-command" | "--dry-run" => options.print_command = true,
+                "--list-modes" => options.list_modes = true,
+                "--dedupe-modes" => options.dedupe_modes = true,
+                "--select-by-name-regex" => {
+                    let pattern = value(&mut args, &arg)?;
+                    options.select_by_name_regex =
+                        Some(Regex::new(&pattern).context(format!("Invalid regex {}", pattern))?);
+                }
+                "--restore-on-term" => options.restore_on_term = true,
+                "--list" => options.list = true,
+                "--active-only" => options.active_only = true,
+                "--auto-rotate-from-edid" => options.auto_rotate_from_edid = true,
+                "--compare-current-json" => {
+                    options.compare_current_json = Some(value(&mut args, &arg)?.into());
+                }
+                "--strategy" => options.strategy = value(&mut args, &arg)?.parse()?,
+                "--arrangement" => options.arrangement = value(&mut args, &arg)?.parse()?,
+                "--sticky-primary" => options.sticky_primary = true,
+                "--health-check" => options.health_check = true,
+                "--auto-mode" => options.auto_mode = true,
+                "--run-screenlayout" => {
+                    options.run_screenlayout = Some(value(&mut args, &arg)?.into());
+                }
+                "--verify" => options.verify = true,
+                "--watch" => options.watch = true,
+                "--normalize-positions" => options.normalize_positions = true,
+                "--include-unknown" => options.include_unknown = true,
+                "--json" => options.json = true,
+                "--nagios" => options.nagios = true,
+                "--debug-model" => options.debug_model = true,
+                "--force-first" => options.force_first = true,
+                "--max-outputs" => {
+                    options.max_outputs = Some(
+                        value(&mut args, &arg)?
+                            .parse()
+                            .context("--max-outputs expects a number of outputs")?,
+                    );
+                }
+                _ => bail!("Unknown argument {}", arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .context(format!("Expected a value after {}", flag))
+}
+
+/// Splits a per output `NAME=VALUE` argument.
+fn assignment(value: &str) -> Result<(String, String)> {
+    let (name, value) = value
+        .split_once('=')
+        .context(format!("Expected NAME=VALUE, found {}", value))?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
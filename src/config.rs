@@ -0,0 +1,175 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// User configuration read from `$XDG_CONFIG_HOME/not-shit-randr/config.toml`.
+///
+/// ```toml
+/// internal_prefixes = ["DSI", "eDP"]
+/// preferred_primary = ["DP-1", "HDMI-1-0"]
+/// disabled_outputs = ["HDMI-2"]
+/// height = 1080
+///
+/// [layouts."HDMI-1-0,eDP-1"]
+/// HDMI-1-0 = { mode = "2560x1440", primary = true }
+/// eDP-1 = { off = true }
+///
+/// [profiles.presenting]
+/// HDMI-1-0 = { mode = "1920x1080", rate = 60.0, primary = true }
+/// eDP-1 = { mode = "1920x1080" }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Connector prefixes of laptop panels, `eDP` and `LVDS` when unset.
+    #[serde(default)]
+    pub internal_prefixes: Option<Vec<String>>,
+    /// Outputs to make primary in order of preference, after any given with `--primary-chain`.
+    #[serde(default)]
+    pub preferred_primary: Vec<String>,
+    /// Outputs that are always turned off and never selected, such as a broken port.
+    #[serde(default)]
+    pub disabled_outputs: Vec<String>,
+    /// Vertical resolution to run every output at when `--height` isn't given.
+    #[serde(default)]
+    pub height: Option<usize>,
+    /// Stored layouts keyed by the signature of the connected outputs they apply to.
+    #[serde(default)]
+    pub layouts: HashMap<String, Layout>,
+    /// Named layouts applied with `--profile`.
+    #[serde(default)]
+    pub profiles: HashMap<String, Layout>,
+}
+
+/// Desired settings for each output of a known set of connected monitors.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Layout {
+    pub outputs: BTreeMap<String, OutputLayout>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputLayout {
+    /// Mode as `WxH`, the output's max mode is used when this is missing.
+    pub mode: Option<String>,
+    /// Refresh rate to pin the mode to, the highest the mode offers when missing.
+    pub rate: Option<f32>,
+    #[serde(default)]
+    pub primary: bool,
+    #[serde(default)]
+    pub off: bool,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("not-shit-randr").join("config.toml"))
+    }
+    /// Reads the config at `path`, a missing file is the same as an empty config.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e).context(format!("Failed to read config {}", path.display())),
+        };
+        Config::from_toml(&text).context(format!("Failed to parse config {}", path.display()))
+    }
+    /// Parses a config, two layouts for the same set of outputs written in a different order
+    /// are an error since there'd be no telling which one applies.
+    pub fn from_toml(text: &str) -> Result<Config> {
+        let config: Config = toml::from_str(text)?;
+        let mut keys = config.layouts.keys().collect::<Vec<&String>>();
+        keys.sort();
+        let mut seen: BTreeMap<String, &String> = BTreeMap::new();
+        for key in keys {
+            if let Some(other) = seen.insert(signature_from_key(key), key) {
+                bail!("Layouts {:?} and {:?} are for the same outputs", other, key);
+            }
+        }
+        Ok(config)
+    }
+    pub fn profile(&self, name: &str) -> Result<&Layout> {
+        self.profiles
+            .get(name)
+            .context(format!("No profile named {} in the config", name))
+    }
+    /// Finds the layout stored for exactly this set of connected outputs, in any order.
+    pub fn match_layout(&self, connected: &[String]) -> Option<&Layout> {
+        let signature = signature(connected);
+        self.layouts
+            .iter()
+            .find(|(key, _)| signature_from_key(key) == signature)
+            .map(|(_, layout)| layout)
+    }
+}
+
+/// Canonical signature of a set of connector names, sorted and comma joined.
+pub fn signature(names: &[String]) -> String {
+    let mut names = names.iter().map(|name| name.trim()).collect::<Vec<&str>>();
+    names.sort_unstable();
+    names.join(",")
+}
+
+fn signature_from_key(key: &str) -> String {
+    signature(&key.split(',').map(String::from).collect::<Vec<String>>())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[layouts."HDMI-1-0,eDP-1"]
+HDMI-1-0 = { mode = "2560x1440", primary = true }
+eDP-1 = { off = true }
+
+[layouts.eDP-1]
+eDP-1 = { mode = "1920x1200" }
+"#;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_match_layout() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let layout = config.match_layout(&names(&["eDP-1", "HDMI-1-0"])).unwrap();
+        assert_eq!(layout.outputs.len(), 2);
+        assert!(layout.outputs["HDMI-1-0"].primary);
+        assert!(layout.outputs["eDP-1"].off);
+        let layout = config.match_layout(&names(&["eDP-1"])).unwrap();
+        assert_eq!(layout.outputs["eDP-1"].mode.as_deref(), Some("1920x1200"));
+    }
+
+    #[test]
+    fn test_duplicate_layout_signatures() {
+        let error = Config::from_toml(
+            r#"
+[layouts."eDP-1,HDMI-1-0"]
+eDP-1 = { off = true }
+
+[layouts."HDMI-1-0,eDP-1"]
+HDMI-1-0 = { off = true }
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Layouts "HDMI-1-0,eDP-1" and "eDP-1,HDMI-1-0" are for the same outputs"#
+        );
+    }
+
+    #[test]
+    fn test_match_layout_none() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        assert!(config.match_layout(&names(&["DP-1", "eDP-1"])).is_none());
+        assert!(config.match_layout(&names(&["HDMI-1-0"])).is_none());
+        assert!(Config::default().match_layout(&names(&["eDP-1"])).is_none());
+    }
+}
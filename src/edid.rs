@@ -0,0 +1,65 @@
+use crate::Rotation;
+use std::collections::HashMap;
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+/// Offset of the first detailed timing descriptor, the panel's native mode.
+const FIRST_TIMING: usize = 54;
+
+/// The raw EDID of each output in `xrandr --verbose`, printed as hex rows under `EDID:`.
+pub fn parse_verbose(verbose: &str) -> HashMap<String, Vec<u8>> {
+    let mut edids = HashMap::new();
+    let mut output = None;
+    let mut in_edid = false;
+    for line in verbose.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            output = line.split_whitespace().next().map(String::from);
+            in_edid = false;
+            continue;
+        }
+        let line = line.trim();
+        if line == "EDID:" {
+            in_edid = true;
+            continue;
+        }
+        if !in_edid {
+            continue;
+        }
+        let Some(bytes) = parse_hex_row(line) else {
+            in_edid = false;
+            continue;
+        };
+        if let Some(output) = &output {
+            edids
+                .entry(output.clone())
+                .or_insert_with(Vec::new)
+                .extend(bytes);
+        }
+    }
+    edids
+}
+
+fn parse_hex_row(row: &str) -> Option<Vec<u8>> {
+    if row.is_empty() || !row.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..row.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(row.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Panels built to be used in portrait, such as many tablet panels, have a native timing
+/// taller than it is wide. Those want turning left to be read the right way up.
+pub fn rotation_hint(edid: &[u8]) -> Option<Rotation> {
+    if edid.len() < 128 || edid[..8] != HEADER {
+        return None;
+    }
+    let timing = &edid[FIRST_TIMING..FIRST_TIMING + 18];
+    // A zero pixel clock marks a display descriptor rather than a timing.
+    if timing[0] == 0 && timing[1] == 0 {
+        return None;
+    }
+    let horizontal = timing[2] as usize | ((timing[4] as usize >> 4) << 8);
+    let vertical = timing[5] as usize | ((timing[7] as usize >> 4) << 8);
+    (vertical > horizontal).then_some(Rotation::Left)
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+use std::io;
+
+/// Failures callers of the library may want to tell apart. These are returned inside
+/// `anyhow::Error` by the runner and `run`, `downcast_ref` recovers them.
+#[derive(Debug)]
+pub enum RandrError {
+    /// xrandr couldn't be started, usually because it isn't installed.
+    XrandrSpawn(io::Error),
+    NonUtf8Output,
+    /// xrandr printed something the parser doesn't understand.
+    Parse(String),
+    NoActiveMonitors,
+}
+
+impl fmt::Display for RandrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RandrError::XrandrSpawn(_) => f.write_str("During launching xrandr an error occured"),
+            RandrError::NonUtf8Output => {
+                f.write_str("Non utf8 characters encountered when parsing xrandr output.")
+            }
+            RandrError::Parse(message) => f.write_str(message),
+            RandrError::NoActiveMonitors => f.write_str("No active monitors found."),
+        }
+    }
+}
+
+impl std::error::Error for RandrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RandrError::XrandrSpawn(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Parses `xrandr` output into [`Monitors`] and builds the [`XrandrCommand`] plans that lay
+//! them out, the `not-shit-randr` binary is a thin layer of option handling on top.
+
+pub mod backend;
+pub mod clock;
+pub mod config;
+pub mod edid;
+pub mod error;
+pub mod lid;
+#[cfg(feature = "listen")]
+pub mod listen;
+pub mod monitors;
+pub mod orientation;
+pub mod plan;
+pub mod provider;
+pub mod resolution;
+pub mod runner;
+pub mod screenlayout;
+pub mod signal;
+pub mod state;
+pub mod watch;
+
+pub use error::RandrError;
+pub use monitors::{Mode, Monitor, Monitors};
+pub use plan::{OutputCommand, Rotation, XrandrCommand};
+pub use resolution::Resolution;
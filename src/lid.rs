@@ -0,0 +1,46 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Somewhere to tell whether the laptop lid is shut.
+pub trait LidSource {
+    fn is_closed(&mut self) -> Result<bool>;
+}
+
+/// The ACPI lid buttons under `/proc/acpi/button/lid`, machines without one count as open.
+pub struct AcpiLid {
+    dir: PathBuf,
+}
+
+impl Default for AcpiLid {
+    fn default() -> AcpiLid {
+        AcpiLid {
+            dir: PathBuf::from("/proc/acpi/button/lid"),
+        }
+    }
+}
+
+impl LidSource for AcpiLid {
+    fn is_closed(&mut self) -> Result<bool> {
+        let lids = match std::fs::read_dir(&self.dir) {
+            Ok(lids) => lids,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).context(format!("Failed to list {}", self.dir.display())),
+        };
+        for lid in lids {
+            let state = lid?.path().join("state");
+            if parse_state(&state)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Reads a `state:      closed` file.
+fn parse_state(path: &Path) -> Result<bool> {
+    let state = std::fs::read_to_string(path)
+        .context(format!("Failed to read lid state {}", path.display()))?;
+    Ok(state
+        .split_once(':')
+        .is_some_and(|(_, state)| state.trim() == "closed"))
+}
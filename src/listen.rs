@@ -0,0 +1,92 @@
+use crate::clock::Clock;
+use crate::runner::Runner;
+use crate::signal::Terminate;
+use crate::XrandrCommand;
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+/// How often to check for termination while nobody is connected.
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+/// How long a connected client may stay silent before it's dropped, so one idle client can't
+/// keep the listener from accepting others or noticing termination.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Accepts connections on the socket at `path` until `terminate` is requested, applying every
+/// plan sent with the `disabled` outputs kept off. A connection being read from is finished
+/// before terminating, or dropped after `READ_TIMEOUT` without a message.
+pub fn serve(
+    path: &Path,
+    runner: &mut impl Runner,
+    clock: &mut impl Clock,
+    disabled: &[String],
+    terminate: &Terminate,
+) -> Result<()> {
+    // A socket left behind by an earlier run would make the bind fail, anything else at the
+    // path is likely a typo and is left alone.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)
+            .context(format!("Failed to remove old socket {}", path.display()))?;
+    }
+    let listener =
+        UnixListener::bind(path).context(format!("Failed to listen on {}", path.display()))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to make the socket non blocking")?;
+    while !terminate.requested() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream
+                    .set_nonblocking(false)
+                    .context("Failed to make the connection blocking")?;
+                stream
+                    .set_read_timeout(Some(READ_TIMEOUT))
+                    .context("Failed to set a read timeout on the connection")?;
+                // A client that sends garbage or goes quiet only loses its own connection.
+                if let Err(e) = handle_connection(stream, runner, disabled) {
+                    eprintln!("Warning: {:#}", e);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => clock.sleep(ACCEPT_POLL),
+            Err(e) => eprintln!("Warning: failed to accept a connection: {}", e),
+        }
+    }
+    std::fs::remove_file(path).context(format!("Failed to remove socket {}", path.display()))
+}
+
+/// Applies each line of the connection as a JSON plan, turning the `disabled` outputs off
+/// whatever it asks of them. A line that isn't a plan, or that xrandr rejects, is reported and
+/// skipped so one bad message doesn't end the session.
+pub fn handle_connection(
+    stream: UnixStream,
+    runner: &mut impl Runner,
+    disabled: &[String],
+) -> Result<()> {
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("Failed to read from the socket")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut plan = match serde_json::from_str::<XrandrCommand>(&line) {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("Warning: skipping message that isn't a plan: {}", e);
+                continue;
+            }
+        };
+        plan.turn_off(disabled);
+        if let Err(e) = plan
+            .checked_command_string()
+            .and_then(|args| runner.xrandr(&args))
+        {
+            eprintln!("Warning: failed to apply plan: {:#}", e);
+        }
+    }
+    Ok(())
+}
@@ -1,205 +1,890 @@
-use anyhow::{Context, Result};
-use core::str;
-use std::process::{exit, Command};
-
-fn launch_xrandr(arg: &str) -> String {
-    let Ok(output) = Command::new("xrandr")
-        .arg(arg)
-        .output()
-        .context("During launching xrandr an error occured")
-        .map_err(|e| {
-            eprintln!("{}", e);
-        })
-    else {
-        exit(1);
-    };
-    match String::from_utf8(output.stdout) {
-        Ok(s) => s,
-        Err(e) => {
+mod cli;
+
+use anyhow::{bail, Context, Result};
+use cli::Options;
+use not_shit_randr::backend::{self, Backend, WlrRandrBackend};
+use not_shit_randr::clock::{Clock, SystemClock};
+use not_shit_randr::config::Config;
+use not_shit_randr::edid;
+use not_shit_randr::lid::{AcpiLid, LidSource};
+#[cfg(feature = "listen")]
+use not_shit_randr::listen;
+use not_shit_randr::monitors::{
+    delete_virtual_command, diff_summaries, is_internal, Arrangement, OutputSummary, State,
+    DEFAULT_INTERNAL_PREFIXES,
+};
+use not_shit_randr::orientation::{ExternalOrientation, OrientationSource};
+use not_shit_randr::plan::{check_command, shell_quote, ApplyOrder};
+use not_shit_randr::provider::Provider;
+use not_shit_randr::runner::{Runner, XrandrRunner};
+use not_shit_randr::screenlayout;
+use not_shit_randr::signal::Terminate;
+use not_shit_randr::state::CycleState;
+use not_shit_randr::watch::{ChangeSource, Debounce, ModifiedTime};
+use not_shit_randr::{Monitor, Monitors, RandrError, XrandrCommand};
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+
+/// How long to wait for `-q` and `--listactivemonitors` to agree when not set on the command
+/// line.
+const DEFAULT_SETTLE_TIMEOUT: Duration = Duration::from_millis(500);
+const SETTLE_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// How often `--watch` asks xrandr which outputs are connected.
+const WATCH_POLL: Duration = Duration::from_secs(1);
+/// How long a change to the connected outputs has to hold before `--watch` acts on it.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+fn query_possible_monitors(runner: &mut impl Runner, strict: bool) -> Result<Monitors> {
+    let possible_monitors = runner.xrandr(&["-q".to_string()])?;
+    let possible_monitors = Monitors::from_query(possible_monitors.trim())
+        .context("Parseing the output of xrandr failed")?;
+    if strict {
+        possible_monitors.check_strict()?;
+    }
+    for warning in &possible_monitors.warnings {
+        eprintln!("{}", warning);
+    }
+    Ok(possible_monitors)
+}
+
+fn query_current_monitors(runner: &mut impl Runner) -> Result<Monitors> {
+    let active_string = runner.xrandr(&["--listactivemonitors".to_string()])?;
+    Ok(Monitors::from_listactivemonitors(active_string.trim())?)
+}
+
+/// Queries both views of the outputs, re-querying while they disagree until `timeout` has
+/// passed. After that the last answers are used as they are.
+fn query_settled_monitors(
+    runner: &mut impl Runner,
+    clock: &mut impl Clock,
+    strict: bool,
+    timeout: Duration,
+) -> Result<(Monitors, Monitors)> {
+    let deadline = clock.now() + timeout;
+    let mut possible_monitors = query_possible_monitors(runner, strict)?;
+    let mut current_monitors = query_current_monitors(runner)?;
+    if let Some(warning) = possible_monitors.active_count_warning(&current_monitors) {
+        eprintln!("{} Waiting for them to settle.", warning);
+    }
+    while possible_monitors
+        .active_count_warning(&current_monitors)
+        .is_some()
+    {
+        if clock.now() >= deadline {
             eprintln!(
-                "Non utf8 characters encountered when parsing xrandr output. {}",
-                e
+                "Warning: outputs did not settle within {}ms, using the last query.",
+                timeout.as_millis()
             );
-            exit(1);
+            break;
         }
+        clock.sleep(SETTLE_RETRY_DELAY);
+        possible_monitors = query_possible_monitors(runner, strict)?;
+        current_monitors = query_current_monitors(runner)?;
     }
+    Ok((possible_monitors, current_monitors))
 }
 
-fn main() {
-    let possible_monitors = launch_xrandr("-q");
-    let Ok(possible_monitors) = Monitors::from_query(possible_monitors.trim())
-        .map_err(|e| eprintln!("Parseing the output of xrandr failed due to {}", e))
-    else {
-        exit(1);
-    };
-    if possible_monitors.monitors.is_empty() {
-        eprintln!("No active monitors found.");
-        exit(1);
+/// Plan stepping `monitor` to the mode after the one recorded in the state file at `path`,
+/// wrapping back to the first mode after the last. The new position is recorded.
+fn cycle_resolution(monitor: &Monitor, path: &Path) -> Result<XrandrCommand> {
+    if monitor.modes.is_empty() {
+        bail!("{} has no modes to cycle through", monitor.name);
     }
-    if possible_monitors.monitors.len() == 1 {
-        exit(0);
+    let index = match CycleState::load(path)? {
+        Some(state) if state.output == monitor.name => (state.index + 1) % monitor.modes.len(),
+        // Start from wherever the output is now.
+        _ => monitor
+            .modes
+            .iter()
+            .position(|mode| mode.current_rate.is_some())
+            .map_or(0, |current| (current + 1) % monitor.modes.len()),
+    };
+    let mode = &monitor.modes[index];
+    let mut output = monitor.output_command(true);
+    output.mode = Some(mode.resolution.into());
+    output.primary = monitor.primary;
+    CycleState {
+        output: monitor.name.clone(),
+        index,
     }
-    let active_string = launch_xrandr("--listactivemonitors");
+    .save(path)?;
+    Ok(XrandrCommand {
+        outputs: vec![output],
+    })
+}
 
-    let Ok(current_monitors) =
-        Monitors::from_listactivemonitors(active_string.trim()).map_err(|e| eprintln!("{}", e))
-    else {
-        exit(1)
+/// Applies `plan`, runs the wrapped command and then puts back the `restore` state, whether or
+/// not the command succeeded. Returns the exit code of the wrapped command.
+fn run_with_restore(
+    runner: &mut impl Runner,
+    plan: Option<&XrandrCommand>,
+    restore: &XrandrCommand,
+    command: &[String],
+) -> Result<i32> {
+    let Some(plan) = plan else {
+        return runner.command(command);
     };
-    if current_monitors.largest().name != possible_monitors.largest().name
-        || current_monitors.monitors.len() > 1
-    {
-        Command::new("xrandr")
-            .args(possible_monitors.largest_on_command_string())
-            .spawn()
-            .unwrap()
-            .wait()
-            .unwrap();
+    runner.xrandr(&plan.command_string())?;
+    let status = runner.command(command);
+    restore_layout(runner, restore)?;
+    status
+}
+
+/// Puts back a layout captured before anything was changed.
+fn restore_layout(runner: &mut impl Runner, restore: &XrandrCommand) -> Result<()> {
+    runner
+        .xrandr(&restore.command_string())
+        .context("Restoring the previous layout failed")?;
+    Ok(())
+}
+
+/// The monitors `--json` reports, leaving out disabled outputs and with `--active-only` the
+/// dark ones.
+fn json_monitors(options: &Options, config: &Config, monitors: &Monitors) -> Monitors {
+    monitors.filtered(|m| {
+        !config.disabled_outputs.contains(&m.name) && (!options.active_only || m.is_active())
+    })
+}
+
+/// The layout active right now, to put back later with the `disabled` outputs still off.
+fn initial_layout(
+    runner: &mut impl Runner,
+    strict: bool,
+    disabled: &[String],
+) -> Result<XrandrCommand> {
+    let possible = query_possible_monitors(runner, strict)?;
+    let mut restore = possible.restore_plan(&query_current_monitors(runner)?);
+    restore.turn_off(disabled);
+    Ok(restore)
+}
+
+/// The config given with `--config`, or the one in the default location.
+fn load_config(options: &Options) -> Result<Config> {
+    match options.config.clone().or_else(Config::default_path) {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::default()),
     }
 }
 
-#[derive(Debug)]
-struct Monitors {
-    monitors: Vec<Monitor>,
+/// Everything `main` does, failures are returned rather than printed so each run reports at
+/// most one error. Returns the exit code.
+fn run(
+    options: &Options,
+    runner: &mut impl Runner,
+    clock: &mut impl Clock,
+    lid: &mut impl LidSource,
+) -> Result<i32> {
+    run_forced(options, runner, clock, lid, options.force_first)
 }
 
-impl Monitors {
-    fn from_query(xrandr_outputs: &str) -> Result<Monitors> {
-        let chunks = Monitors::chunks_from_activemonitors(xrandr_outputs);
-        let alive_monitors = chunks
+/// `run`, with `force` applying the layout even when it is already in place.
+fn run_forced(
+    options: &Options,
+    runner: &mut impl Runner,
+    clock: &mut impl Clock,
+    lid: &mut impl LidSource,
+    force: bool,
+) -> Result<i32> {
+    if options.health_check {
+        // Monitoring only looks at the exit code, so failures aren't printed either.
+        let healthy = runner
+            .xrandr(&["-q".to_string()])
+            .and_then(|output| Ok(Monitors::from_query(output.trim())?))
+            .is_ok_and(|monitors| !monitors.monitors.is_empty());
+        return Ok(if healthy { 0 } else { 1 });
+    }
+    if options.debug_model {
+        println!(
+            "{}",
+            Monitors::debug_model(&runner.xrandr(&["-q".to_string()])?)
+        );
+        return Ok(0);
+    }
+    if options.nagios {
+        let (code, status) = load_config(options)
+            .and_then(|config| {
+                let output = runner.xrandr(&["-q".to_string()])?;
+                let monitors = Monitors::from_query(output.trim())?;
+                Ok(monitors.filtered(|m| !config.disabled_outputs.contains(&m.name)))
+            })
+            .map(|monitors| monitors.nagios_status())
+            .unwrap_or_else(|e| (2, format!("CRITICAL - {:#}", e)));
+        println!("{}", status);
+        return Ok(code);
+    }
+    let config = load_config(options)?;
+    let internal_prefixes = options
+        .internal_prefixes
+        .clone()
+        .or_else(|| config.internal_prefixes.clone())
+        .unwrap_or_else(|| DEFAULT_INTERNAL_PREFIXES.map(String::from).to_vec());
+    if let Some(path) = &options.run_screenlayout {
+        let script =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let args = screenlayout::parse_script(&script)
+            .context(format!("Failed to parse {}", path.display()))?;
+        let disabled = screenlayout::enabled_outputs(&args)
             .into_iter()
-            .skip(1)
-            .filter(|vec| !vec[0].contains("disconnected"))
-            .map(Monitor::parse_monitor_from_listactivemonitors_chunk)
-            .collect::<Result<Vec<Monitor>>>()
-            .context("Failure during parsing out monitor details")?;
-        Ok(Monitors {
-            monitors: alive_monitors,
-        })
+            .filter(|name| config.disabled_outputs.contains(name))
+            .collect::<Vec<String>>();
+        if !disabled.is_empty() {
+            bail!(
+                "{} turns on {}, which disabled_outputs keeps off",
+                path.display(),
+                disabled.join(", ")
+            );
+        }
+        if options.print_command {
+            let words = std::iter::once("xrandr").chain(args.iter().map(String::as_str));
+            println!(
+                "{}",
+                words.map(shell_quote).collect::<Vec<String>>().join(" ")
+            );
+            return Ok(0);
+        }
+        check_command(&args)?;
+        runner.xrandr(&args)?;
+        return Ok(0);
     }
-    fn chunks_from_activemonitors(xrandr_outputs: &str) -> Vec<Vec<String>> {
-        let mut chunks = Vec::new();
-        let mut lines: Vec<String> = xrandr_outputs.trim().lines().map(String::from).collect();
-        let mut peak;
-        while lines.len() > 1 {
-            peak = 1;
-            let mut peak_line = &lines[peak];
-            while !peak_line.contains("connected") {
-                match lines.get(peak) {
-                    Some(p) => {
-                        peak_line = p;
-                    }
-                    None => break,
-                };
-                peak += 1
+    if let Some(path) = &options.listen {
+        #[cfg(feature = "listen")]
+        {
+            let initial = options
+                .restore_on_term
+                .then(|| initial_layout(runner, options.strict, &config.disabled_outputs))
+                .transpose()?;
+            listen::serve(
+                path,
+                runner,
+                clock,
+                &config.disabled_outputs,
+                &Terminate::install()?,
+            )?;
+            if let Some(initial) = initial {
+                restore_layout(runner, &initial)?;
             }
-            // Double allocation of the print could probably just steal this out of the buffer.
-            chunks.push(lines.drain(..peak).collect());
+            return Ok(0);
         }
-        chunks
+        #[cfg(not(feature = "listen"))]
+        bail!(
+            "Can't listen on {}, rebuild with --features listen",
+            path.display()
+        );
     }
-    fn largest(&self) -> &Monitor {
-        let mut biggest_monitor = &self.monitors[0];
-        for monitor in &self.monitors {
-            if monitor.width > biggest_monitor.width {
-                biggest_monitor = monitor;
-            }
+    let possible_monitors = query_possible_monitors(runner, options.strict)?;
+    if options.print_connected_signature {
+        println!("{}", possible_monitors.signature());
+        return Ok(0);
+    }
+    if options.ppi_report {
+        for line in possible_monitors.ppi_report() {
+            println!("{}", line);
         }
-        biggest_monitor
+        return Ok(0);
     }
-    fn largest_on_command_string(&self) -> Vec<String> {
-        let name = self.largest().name.clone();
-        self.monitors
+    if options.list {
+        if possible_monitors.monitors.is_empty() {
+            eprintln!("no active monitors");
+            return Ok(1);
+        }
+        let current = query_current_monitors(runner)?;
+        let selected = select_target(
+            options,
+            &config,
+            &internal_prefixes,
+            &possible_monitors,
+            &current,
+            lid,
+        )?
+        .map(|m| m.name.as_str());
+        for line in possible_monitors.list(options.active_only, selected) {
+            println!("{}", line);
+        }
+        return Ok(0);
+    }
+    if options.list_modes {
+        for line in possible_monitors.list_modes(options.dedupe_modes, options.active_only) {
+            println!("{}", line);
+        }
+        return Ok(0);
+    }
+    if let Some(path) = &options.compare_current_json {
+        let baseline = std::fs::read_to_string(path)
+            .context(format!("Failed to read baseline {}", path.display()))?;
+        let baseline: Vec<OutputSummary> = serde_json::from_str(&baseline)
+            .context(format!("Failed to parse baseline {}", path.display()))?;
+        let differences = diff_summaries(&baseline, &possible_monitors.summary());
+        for difference in &differences {
+            println!("{}", difference);
+        }
+        return Ok(if differences.is_empty() { 0 } else { 1 });
+    }
+    if options.json {
+        let current = query_current_monitors(runner)?;
+        let selected = select_target(
+            options,
+            &config,
+            &internal_prefixes,
+            &possible_monitors,
+            &current,
+            lid,
+        )?;
+        let reported = json_monitors(options, &config, &possible_monitors);
+        let report = reported.report(selected.map(|m| m.name.as_str()));
+        println!("{}", serde_json::to_string(&report)?);
+        return Ok(0);
+    }
+    if options.summary_json {
+        println!("{}", serde_json::to_string(&possible_monitors.summary())?);
+        return Ok(0);
+    }
+    if let Some((name, parts)) = &options.split {
+        let current = query_current_monitors(runner)?;
+        let monitor = current
+            .find(name)
+            .context(format!("Can't split {}, it isn't active", name))?;
+        runner.xrandr(&monitor.split_command(*parts)?)?;
+        return Ok(0);
+    }
+    if !options.labels.is_empty() {
+        let current = query_current_monitors(runner)?;
+        let mut args = Vec::new();
+        for (name, label) in &options.labels {
+            let monitor = current
+                .find(name)
+                .context(format!("Can't label {}, it isn't active", name))?;
+            args.extend(monitor.label_command(label));
+        }
+        runner.xrandr(&args)?;
+        return Ok(0);
+    }
+    if options.delete_virtual {
+        let listmonitors = runner.xrandr(&["--listmonitors".to_string()])?;
+        let args = delete_virtual_command(&listmonitors)?;
+        if !args.is_empty() {
+            runner.xrandr(&args)?;
+        }
+        return Ok(0);
+    }
+    if possible_monitors.monitors.is_empty() {
+        return Err(RandrError::NoActiveMonitors.into());
+    }
+    let explicit_layout = options.cycle_resolution
+        || options.force_mode_on_all.is_some()
+        || options.orientation_source.is_some()
+        || options.auto_rotate_from_edid
+        || options.profile.is_some()
+        || !options.modes.is_empty()
+        || !options.rates.is_empty()
+        || options.target_resolution.is_some()
+        || options.max_resolution.is_some()
+        || options.height.or(config.height).is_some()
+        || options.prefer_higher_refresh_over_resolution
+        || !options.panning.is_empty()
+        || !options.rotations.is_empty()
+        || options.scale.is_some()
+        || !options.properties.is_empty()
+        || !options.keep_only.is_empty()
+        || config.match_layout(&possible_monitors.names()).is_some();
+    if !explicit_layout
+        && !force
+        && options.restore_on_exit.is_empty()
+        && possible_monitors.monitors.len() == 1
+    {
+        return Ok(0);
+    }
+    let (mut possible_monitors, current_monitors) = query_settled_monitors(
+        runner,
+        clock,
+        options.strict,
+        options.settle_timeout.unwrap_or(DEFAULT_SETTLE_TIMEOUT),
+    )?;
+    if let Some(provider) = &options.provider {
+        let providers = Provider::parse_list(&runner.xrandr(&["--listproviders".to_string()])?)?;
+        possible_monitors = possible_monitors.on_provider(&providers, provider)?;
+        if possible_monitors.monitors.is_empty() {
+            bail!("No monitors connected to provider {}.", provider);
+        }
+    }
+    let mut from_profile = false;
+    let plan = if options.cycle_resolution {
+        let primary = current_monitors
+            .monitors
+            .iter()
+            .filter(|m| m.primary)
+            .find_map(|m| possible_monitors.find(&m.name))
+            .or_else(|| possible_monitors.monitors.iter().find(|m| m.primary))
+            .or_else(|| possible_monitors.largest());
+        let path = CycleState::default_path().context("Can't find a directory for state")?;
+        primary.map(|primary| cycle_resolution(primary, &path))
+    } else if let Some(mode) = options.force_mode_on_all {
+        let (plan, warnings) = possible_monitors.force_mode_plan(mode);
+        warnings.iter().for_each(|warning| eprintln!("{}", warning));
+        Some(Ok(plan))
+    } else if !options.keep_only.is_empty() {
+        Some(possible_monitors.keep_only_plan(&options.keep_only))
+    } else if options.clone_primary_to_all {
+        // Keep mirroring whatever is primary right now, only picking one if nothing is.
+        let primary = current_monitors
+            .monitors
             .iter()
-            .flat_map(|m| m.command_string(m.name == name))
-            .collect::<Vec<String>>()
-    }
-    fn from_listactivemonitors(listactivemonitors: &str) -> Result<Monitors> {
-        let mut monitors = Vec::new();
-        for line in listactivemonitors.lines().skip(1) {
-            let mut line_iter = line.split(' ');
-            let name = line_iter.next_back().context(format!(
-                "Expected to parse name. Found no whitespace {}",
-                line
-            ))?;
-            line_iter.next_back();
-            let width_height = line_iter
-                .next_back()
-                .context(format!("Expect mode after name {}.", line))?;
-            let (width, height) = width_height.split_once('x').context(format!(
-                "Expect to get both width and height from split {}.",
-                line
-            ))?;
-            let width = width
-                .split("/")
-                .next()
-                .context(format!("Stripping / out of output failed {}", width))?
-                .parse()?;
-            let height = height
-                .split("/")
-                .next()
-                .context(format!("Stripping / out of output failed {}", width))?
-                .parse()?;
-            monitors.push(Monitor {
-                name: name.into(),
-                height,
-                width,
+            .filter(|m| m.primary)
+            .find_map(|m| possible_monitors.find(&m.name))
+            .or_else(|| possible_monitors.largest());
+        primary.map(|primary| possible_monitors.clone_plan(&primary.name))
+    } else if let Some(profile) = &options.profile {
+        from_profile = true;
+        Some(
+            config
+                .profile(profile)
+                .and_then(|layout| possible_monitors.layout_plan(layout)),
+        )
+    } else {
+        config
+            .match_layout(&possible_monitors.names())
+            .map(|layout| possible_monitors.layout_plan(layout))
+    };
+    let plan = plan
+        .transpose()
+        .context("Building the requested layout failed")?;
+    // Only the automatic extend places outputs, rotating one there moves those after it.
+    let side_by_side = plan.is_none() && options.arrangement == Arrangement::Extend;
+    let mut plan = match plan {
+        Some(plan) => plan,
+        None => {
+            let target = select_target(
+                options,
+                &config,
+                &internal_prefixes,
+                &possible_monitors,
+                &current_monitors,
+                lid,
+            )?
+            .with_context(|| match options.primary_must_support {
+                Some((width, height)) => format!(
+                    "No connected monitor of at least {} pixels supports {}x{}.",
+                    options.min_area, width, height
+                ),
+                None => format!(
+                    "No connected monitor has a mode of at least {} pixels.",
+                    options.min_area
+                ),
+            })?;
+            if let Some(warning) = possible_monitors.duplicate_primary_warning(&target.name) {
+                if options.strict {
+                    bail!(warning);
+                }
+                eprintln!("{}", warning);
+            }
+            possible_monitors.arranged_plan(options.arrangement, &target.name)
+        }
+    };
+    if let Some(target) = options.target_resolution {
+        possible_monitors.target_modes(&mut plan, target);
+    }
+    if let Some(height) = options.height.or(config.height) {
+        for warning in possible_monitors.height_modes(&mut plan, height) {
+            eprintln!("{}", warning);
+        }
+    }
+    if options.prefer_higher_refresh_over_resolution {
+        possible_monitors.fastest_modes(&mut plan);
+    }
+    // Modes given on the command line win over both profiles and the automatic selection.
+    plan.override_modes(&possible_monitors.resolve_modes(&options.modes)?)?;
+    if let Some(max) = options.max_resolution {
+        for warning in possible_monitors.cap_modes(&mut plan, max) {
+            eprintln!("{}", warning);
+        }
+    }
+    plan.turn_off(&config.disabled_outputs);
+    plan.set_panning(&options.panning)?;
+    plan.set_rotations(&options.rotations)?;
+    plan.set_properties(&options.properties)?;
+    if let Some(scale) = options.scale {
+        plan.scale_primary(scale)?;
+    }
+    possible_monitors.set_highest_rates(&mut plan);
+    if options.prefer_fractional {
+        possible_monitors.prefer_fractional_rates(&mut plan);
+    }
+    if from_profile && options.strip_profile_rates {
+        plan.clear_rates();
+    }
+    possible_monitors.set_rates(&mut plan, &options.rates)?;
+    if options.no_primary {
+        plan.clear_primary();
+    }
+    plan.skip_unchanged_primary(&possible_monitors);
+    if options.auto_mode {
+        plan.use_auto_mode();
+    }
+    if let Some(source) = &options.orientation_source {
+        match ExternalOrientation::new(source).orientation() {
+            Ok(rotation) => plan.rotate_internal(rotation, &internal_prefixes),
+            Err(e) => eprintln!("Warning: ignoring the orientation source, {:#}", e),
+        }
+    }
+    if options.auto_rotate_from_edid {
+        let verbose = runner.xrandr(&["--verbose".to_string()])?;
+        plan.rotate_from_edid(&edid::parse_verbose(&verbose));
+        // The real pixel clocks make the bandwidth warnings exact.
+        possible_monitors.merge_verbose(&verbose);
+    }
+    if side_by_side {
+        plan.place_side_by_side();
+    }
+    if options.normalize_positions {
+        plan.normalize_positions();
+    }
+    plan.order_outputs(options.apply_order);
+    let plan = (force
+        || plan.needs_change(&current_monitors)
+        || plan.changes_rotation(&possible_monitors))
+    .then_some(plan);
+    if options.print_command {
+        if let Some(plan) = plan {
+            println!("{}", plan.shell_command());
+        }
+        return Ok(0);
+    }
+    if !options.restore_on_exit.is_empty() {
+        let mut restore = possible_monitors.restore_plan(&current_monitors);
+        restore.turn_off(&config.disabled_outputs);
+        restore.order_outputs(options.apply_order);
+        // While this is installed a SIGTERM or SIGINT only stops the wrapped command, the
+        // restore still runs.
+        let terminate = options
+            .restore_on_term
+            .then(Terminate::install)
+            .transpose()?;
+        let code = run_with_restore(runner, plan.as_ref(), &restore, &options.restore_on_exit)?;
+        if terminate.as_ref().is_some_and(Terminate::requested) {
+            eprintln!("Stopped by a signal, the previous layout has been restored.");
+        }
+        return Ok(code);
+    }
+    if let Some(plan) = plan {
+        if let Some(warning) = possible_monitors
+            .screen
+            .and_then(|screen| plan.exceeds_screen(&screen))
+        {
+            eprintln!("{}", warning);
+        }
+        if let Some(warning) = options
+            .max_outputs
+            .and_then(|max| plan.exceeds_output_limit(max))
+        {
+            eprintln!("{}", warning);
+        }
+        for warning in possible_monitors.bandwidth_warnings(&plan) {
+            eprintln!("{}", warning);
+        }
+        apply_plan(runner, &plan, options.verify)?;
+    }
+    Ok(0)
+}
+
+/// Runs the plan, with `verify` the active outputs are queried again afterwards since xrandr
+/// can exit successfully without having changed anything.
+fn apply_plan(runner: &mut impl Runner, plan: &XrandrCommand, verify: bool) -> Result<()> {
+    runner.xrandr(&plan.checked_command_string()?)?;
+    if verify {
+        let current = query_current_monitors(runner)?;
+        if !plan.matches(&current) {
+            bail!(
+                "xrandr didn't apply the layout, the active outputs are now {}",
+                current.names().join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The options `run_backend` can't honour, by their flag.
+fn unsupported_by_backend(options: &Options) -> Vec<&'static str> {
+    [
+        ("--watch", options.watch),
+        ("--listen", options.listen.is_some()),
+        ("--profile", options.profile.is_some()),
+        ("--keep-only", !options.keep_only.is_empty()),
+        ("--force-mode-on-all", options.force_mode_on_all.is_some()),
+        ("--clone-primary-to-all", options.clone_primary_to_all),
+        ("--cycle-resolution", options.cycle_resolution),
+        ("--restore-on-exit", !options.restore_on_exit.is_empty()),
+        ("--restore-on-term", options.restore_on_term),
+        ("--run-screenlayout", options.run_screenlayout.is_some()),
+        ("--provider", options.provider.is_some()),
+        ("--strict", options.strict),
+        ("--settle-timeout", options.settle_timeout.is_some()),
+        (
+            "--apply-order",
+            options.apply_order != ApplyOrder::default(),
+        ),
+        ("--force-first", options.force_first),
+        ("--rate", !options.rates.is_empty()),
+        ("--max-resolution", options.max_resolution.is_some()),
+        ("--target-resolution", options.target_resolution.is_some()),
+        ("--height", options.height.is_some()),
+        (
+            "--prefer-higher-refresh-over-resolution",
+            options.prefer_higher_refresh_over_resolution,
+        ),
+        ("--prefer-fractional", options.prefer_fractional),
+        ("--panning", !options.panning.is_empty()),
+        ("--rotate", !options.rotations.is_empty()),
+        ("--scale", options.scale.is_some()),
+        ("--set", !options.properties.is_empty()),
+        ("--orientation-source", options.orientation_source.is_some()),
+        ("--auto-rotate-from-edid", options.auto_rotate_from_edid),
+        ("--auto-mode", options.auto_mode),
+        ("--normalize-positions", options.normalize_positions),
+        ("--verify", options.verify),
+        ("--split", options.split.is_some()),
+        ("--label", !options.labels.is_empty()),
+        ("--delete-virtual", options.delete_virtual),
+        ("--ppi-report", options.ppi_report),
+        ("--summary-json", options.summary_json),
+        (
+            "--compare-current-json",
+            options.compare_current_json.is_some(),
+        ),
+        ("--debug-model", options.debug_model),
+        ("--max-outputs", options.max_outputs.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, given)| given.then_some(flag))
+    .collect()
+}
+
+/// The automatic selection on its own, for backends that can only list outputs and apply a
+/// plan. The X11 specific options are an error, the listing and printing ones never apply.
+fn run_backend(
+    options: &Options,
+    backend: &mut impl Backend,
+    lid: &mut impl LidSource,
+) -> Result<i32> {
+    let unsupported = unsupported_by_backend(options);
+    if !unsupported.is_empty() {
+        bail!("Not supported with wlr-randr: {}", unsupported.join(", "));
+    }
+    if options.health_check {
+        let healthy = backend
+            .query()
+            .is_ok_and(|monitors| !monitors.monitors.is_empty());
+        return Ok(if healthy { 0 } else { 1 });
+    }
+    let config = load_config(options)?;
+    if options.nagios {
+        let (code, status) = backend
+            .query()
+            .map(|monitors| {
+                monitors
+                    .filtered(|m| !config.disabled_outputs.contains(&m.name))
+                    .nagios_status()
             })
+            .unwrap_or_else(|e| (2, format!("CRITICAL - {:#}", e)));
+        println!("{}", status);
+        return Ok(code);
+    }
+    let internal_prefixes = options
+        .internal_prefixes
+        .clone()
+        .or_else(|| config.internal_prefixes.clone())
+        .unwrap_or_else(|| DEFAULT_INTERNAL_PREFIXES.map(String::from).to_vec());
+    let monitors = backend.query()?;
+    if options.print_connected_signature {
+        println!("{}", monitors.signature());
+        return Ok(0);
+    }
+    if options.list_modes {
+        for line in monitors.list_modes(options.dedupe_modes, options.active_only) {
+            println!("{}", line);
         }
-        Ok(Monitors { monitors })
+        return Ok(0);
+    }
+    if options.list && monitors.monitors.is_empty() {
+        eprintln!("no active monitors");
+        return Ok(1);
+    }
+    let active = monitors.filtered(|m| m.is_active());
+    let target = select_target(
+        options,
+        &config,
+        &internal_prefixes,
+        &monitors,
+        &active,
+        lid,
+    )?;
+    if options.list {
+        for line in monitors.list(options.active_only, target.map(|m| m.name.as_str())) {
+            println!("{}", line);
+        }
+        return Ok(0);
+    }
+    if options.json {
+        let reported = json_monitors(options, &config, &monitors);
+        let report = reported.report(target.map(|m| m.name.as_str()));
+        println!("{}", serde_json::to_string(&report)?);
+        return Ok(0);
+    }
+    let target = target.context(format!(
+        "No connected monitor has a mode of at least {} pixels.",
+        options.min_area
+    ))?;
+    let mut plan = monitors.arranged_plan(options.arrangement, &target.name);
+    plan.override_modes(&monitors.resolve_modes(&options.modes)?)?;
+    plan.turn_off(&config.disabled_outputs);
+    monitors.set_highest_rates(&mut plan);
+    if options.no_primary {
+        plan.clear_primary();
     }
+    if options.print_command {
+        let words = backend.command_line(&plan)?;
+        let words = words.iter().map(|word| shell_quote(word));
+        println!("{}", words.collect::<Vec<String>>().join(" "));
+        return Ok(0);
+    }
+    backend.apply(&plan)?;
+    Ok(0)
+}
+
+/// The monitor the automatic selection picks out of `possible`, `None` when nothing is left to
+/// pick. `--json` and `--list` report the same pick a real run would make.
+fn select_target<'a>(
+    options: &Options,
+    config: &Config,
+    internal_prefixes: &[String],
+    possible: &'a Monitors,
+    current: &Monitors,
+    lid: &mut impl LidSource,
+) -> Result<Option<&'a Monitor>> {
+    let selectable = possible.filtered(|m| !config.disabled_outputs.contains(&m.name));
+    // Virtual outputs often report a tiny mode and should never be picked, nor should outputs
+    // xrandr can't tell are plugged in unless asked for.
+    let mut candidates = selectable.filtered(|m| {
+        m.area() >= options.min_area
+            && (options.include_unknown || m.state == State::Connected)
+            && options
+                .primary_must_support
+                .is_none_or(|(width, height)| m.supports_mode(width, height))
+    });
+    if let Some(pattern) = &options.select_by_name_regex {
+        candidates = candidates.matching(pattern)?;
+    }
+    let lid_closed = lid.is_closed().unwrap_or_else(|e| {
+        eprintln!("Warning: assuming the lid is open, {:#}", e);
+        false
+    });
+    let target = selectable
+        .pick_preferred(&options.primary_chain)
+        .or_else(|| selectable.pick_preferred(&config.preferred_primary))
+        .or_else(|| {
+            let picked =
+                candidates.select_with_lid(options.strategy, lid_closed, internal_prefixes)?;
+            // A closed laptop panel stays out of the running even when it was primary.
+            let current = current
+                .monitors
+                .iter()
+                .find(|m| m.primary)
+                .filter(|m| !(lid_closed && is_internal(&m.name, internal_prefixes)));
+            Some(match current.filter(|_| options.sticky_primary) {
+                Some(current) => candidates.sticky(picked, &current.name),
+                None => picked,
+            })
+        });
+    Ok(target.and_then(|target| possible.find(&target.name)))
 }
 
-#[derive(Debug)]
-struct Monitor {
-    height: usize,
-    width: usize,
-    name: String,
+/// Signature of the connected outputs, without the parse warnings a full run prints.
+fn connected_signature(runner: &mut impl Runner) -> Result<String> {
+    let output = runner.xrandr(&["-q".to_string()])?;
+    Ok(Monitors::from_query(output.trim())?.signature())
 }
-impl Monitor {
-    fn command_string(&self, on: bool) -> Vec<String> {
-        if on {
-            return vec![
-                "--output".into(),
-                self.name.clone(),
-                "--mode".into(),
-                format!("{}x{}", self.width, self.height),
-            ];
-        };
-        vec!["--output".into(), self.name.clone(), "--off".into()]
-    }
-    fn parse_monitor_from_listactivemonitors_chunk(chunk: impl AsRef<[String]>) -> Result<Monitor> {
-        let chunk = chunk.as_ref();
-        let (name, _) = chunk[0]
-            .split_once(' ')
-            .context(format!("Splitting line for name failed: {:?}", &chunk))?;
-        let max_res = chunk[1].trim();
-        let (max_res, refreshrate) = max_res.split_once(' ').context(format!(
-            "Can't find max_refreshrate and resolution from: {}",
-            max_res
-        ))?;
-        let (_, _) = refreshrate
-            .trim()
-            .split_once(' ')
-            .context(format!(
-                "Couldn't parse refreshrate from string {}",
-                refreshrate
-            ))
-            .unwrap_or_else(|_| (refreshrate.trim(), ""));
-        let (width, height) = max_res
-            .split_once('x')
-            .context(format!("Expect reslotion to be widthxheight: {}", max_res))?;
-        let width: usize = width
-            .parse()
-            .context("Height and width should be well bounded integers.")?;
-        let height = height
-            .parse()
-            .context("Height and width should be well bounded integers.")?;
-        Ok(Monitor {
-            name: String::from(name),
-            width,
-            height,
+
+/// Runs once, then again every time the set of connected outputs changes, until `terminate`
+/// is requested. Failed runs are reported without stopping the watch. The first run asserts
+/// the layout even if it is already in place, later ones skip layouts that wouldn't change.
+/// With `--restore-on-term` the layout from before the first run is put back once stopped.
+fn watch(
+    options: &Options,
+    runner: &mut impl Runner,
+    clock: &mut impl Clock,
+    lid: &mut impl LidSource,
+    config: &mut impl ChangeSource,
+    terminate: &Terminate,
+) -> Result<i32> {
+    let initial = options
+        .restore_on_term
+        .then(|| {
+            let config = load_config(options)?;
+            initial_layout(runner, options.strict, &config.disabled_outputs)
         })
+        .transpose()?;
+    if let Err(e) = run_forced(options, runner, clock, lid, true) {
+        eprintln!("Warning: {:#}", e);
+    }
+    let mut debounce = Debounce::new(connected_signature(runner)?, WATCH_DEBOUNCE);
+    while !terminate.requested() {
+        clock.sleep(WATCH_POLL);
+        let signature = match connected_signature(runner) {
+            Ok(signature) => signature,
+            Err(e) => {
+                eprintln!("Warning: {:#}", e);
+                continue;
+            }
+        };
+        let reload = config.changed();
+        if debounce.observe(signature.clone(), clock.now()) {
+            eprintln!(
+                "Connected outputs are now {}, choosing the layout again.",
+                signature
+            );
+        } else if reload {
+            eprintln!("Config changed, choosing the layout again.");
+        } else {
+            continue;
+        }
+        if let Err(e) = run_forced(options, runner, clock, lid, false) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+    if let Some(initial) = initial {
+        restore_layout(runner, &initial)?;
+    }
+    Ok(0)
+}
+
+fn main() {
+    let result = Options::parse(std::env::args().skip(1)).and_then(|options| {
+        if backend::use_wlr_randr()? {
+            run_backend(&options, &mut WlrRandrBackend, &mut AcpiLid::default())
+        } else if options.watch {
+            let config = options.config.clone().or_else(Config::default_path);
+            watch(
+                &options,
+                &mut XrandrRunner,
+                &mut SystemClock,
+                &mut AcpiLid::default(),
+                &mut ModifiedTime::new(config.unwrap_or_default()),
+                &Terminate::install()?,
+            )
+        } else {
+            run(
+                &options,
+                &mut XrandrRunner,
+                &mut SystemClock,
+                &mut AcpiLid::default(),
+            )
+        }
+    });
+    match result {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("{:#}", e);
+            exit(1);
+        }
     }
 }
 
@@ -207,94 +892,1447 @@ impl Monitor {
 mod test {
 
     use super::*;
+    use not_shit_randr::backend::XrandrBackend;
+    use not_shit_randr::plan::parse_mode;
+    use not_shit_randr::resolution::Resolution;
+    use std::time::Instant;
 
-    const OUTPUT: &str = "
-Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384
-eDP-1 connected primary (normal left inverted right x axis y axis)
-   1920x1200     60.10 +  60.10    40.06
-   1920x1080     60.10
-   1600x1200     60.10
-   1680x1050     60.10
-   1400x1050     60.10
-   1600x900      60.10
-   1280x1024     60.10
-   1400x900      60.10
-   1280x960      60.10
-   1440x810      60.10
-   1368x768      60.10
-   1280x800      60.10
-   1280x720      60.10
-   1024x768      60.10
-   960x720       60.10
-   928x696       60.10
-   896x672       60.10
-   1024x576      60.10
-   960x600       60.10
-   960x540       60.10
-   800x600       60.10
-   840x525       60.10
-   864x486       60.10
-   700x525       60.10
-   800x450       60.10
-   640x512       60.10
-   700x450       60.10
-   640x480       60.10
-   720x405       60.09
-   684x384       60.10
-   640x360       60.09
-   512x384       60.10
-   512x288       60.09
-   480x270       60.09
-   400x300       60.10
-   432x243       60.09
-   320x240       60.10
-   360x202       60.09
-   320x180       60.09
-DP-1 disconnected (normal left inverted right x axis y axis)
-HDMI-1 disconnected (normal left inverted right x axis y axis)
-DP-2 disconnected (normal left inverted right x axis y axis)
-HDMI-2 disconnected (normal left inverted right x axis y axis)
-DP-3 disconnected (normal left inverted right x axis y axis)
-HDMI-3 disconnected (normal left inverted right x axis y axis)
-HDMI-4 disconnected (normal left inverted right x axis y axis)
-DP-1-0 disconnected (normal left inverted right x axis y axis)
-DP-1-1 disconnected (normal left inverted right x axis y axis)
-DP-1-2 disconnected (normal left inverted right x axis y axis)
-DP-1-3 disconnected (normal left inverted right x axis y axis)
-HDMI-1-0 connected 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
-   2560x1440     59.95*+
-   2048x1080     60.00
-   1920x1200     59.88
-   1920x1080     60.00    59.94    50.00
-   1680x1050     59.95
-   1600x1200     60.00
-   1280x1024     75.02    60.02
-   1280x800      59.81
-   1280x720      59.94    50.00
-   1152x864      75.00
-   1024x768      75.03    60.00
-   800x600       75.00    60.32
-   720x576       50.00
-   720x480       59.94
-   640x480       75.00    59.94    59.93";
-
-    #[test]
-    fn test_parse() {
-        let chunks = Monitors::chunks_from_activemonitors(OUTPUT);
-        let chunk_str = chunks
-            .into_iter()
-            .flatten()
-            .collect::<Vec<String>>()
-            .join("\n");
-        assert_eq!(chunk_str.trim(), OUTPUT.trim());
+    const OUTPUT: &str = include_str!("../tests/fixtures/sample.txt");
+
+    /// Records every call instead of touching the real displays.
+    #[derive(Default)]
+    struct FakeRunner {
+        calls: Vec<Vec<String>>,
+        exit_code: i32,
+        xrandr_fails: bool,
+        /// What every xrandr call prints.
+        output: String,
+    }
+
+    impl Runner for FakeRunner {
+        fn xrandr(&mut self, args: &[String]) -> Result<String> {
+            self.calls.push([&["xrandr".to_string()], args].concat());
+            if self.xrandr_fails {
+                bail!("Can't open display");
+            }
+            Ok(self.output.clone())
+        }
+        fn command(&mut self, command: &[String]) -> Result<i32> {
+            self.calls.push(command.to_vec());
+            Ok(self.exit_code)
+        }
+    }
+
+    #[test]
+    fn test_restore_on_exit() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        let plan = monitors.keep_only_plan(&["eDP-1".to_string()]).unwrap();
+        let restore = monitors.restore_plan(&current);
+        let command = vec!["false".to_string()];
+        let mut runner = FakeRunner {
+            exit_code: 1,
+            ..FakeRunner::default()
+        };
+        let code = run_with_restore(&mut runner, Some(&plan), &restore, &command).unwrap();
+        assert_eq!(code, 1);
+        assert_eq!(runner.calls.len(), 3);
+        assert_eq!(runner.calls[1], command);
+        assert_eq!(
+            runner.calls[2],
+            vec![
+                "xrandr",
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--pos",
+                "0x0",
+                "--primary"
+            ]
+        );
+    }
+
+    /// Fake xrandr holding the active outputs in memory, applied plans show up in the next
+    /// `--listactivemonitors` query the same way real xrandr would report them.
+    struct SimulatedXrandr {
+        active: Vec<Monitor>,
+        /// Accept every change without applying it, like a driver silently refusing.
+        ignores_changes: bool,
+        /// How many calls tried to change the layout.
+        applied: usize,
+        /// What `-q` prints, the sample when `None`.
+        query: Option<String>,
+    }
+
+    impl SimulatedXrandr {
+        fn with_active(listactivemonitors: &str) -> SimulatedXrandr {
+            SimulatedXrandr {
+                active: Monitors::from_listactivemonitors(listactivemonitors)
+                    .unwrap()
+                    .monitors,
+                ignores_changes: false,
+                applied: 0,
+                query: None,
+            }
+        }
+        fn apply(&mut self, args: &[String]) {
+            let mut args = args.iter();
+            let mut name = String::new();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--output" => name = args.next().unwrap().clone(),
+                    "--off" => self.active.retain(|m| m.name != name),
+                    "--mode" => {
+                        let (width, height) = parse_mode(args.next().unwrap()).unwrap();
+                        if !self.active.iter().any(|m| m.name == name) {
+                            let line = format!(" 0: +{} {}x{}+0+0  {}", name, width, height, name);
+                            let monitors = format!("Monitors: 1\n{}", line);
+                            self.active.extend(
+                                Monitors::from_listactivemonitors(&monitors)
+                                    .unwrap()
+                                    .monitors,
+                            );
+                        }
+                        let monitor = self.active.iter_mut().find(|m| m.name == name).unwrap();
+                        monitor.resolution = Resolution::new(width, height);
+                    }
+                    "--pos" => {
+                        let (x, y) = args.next().unwrap().split_once('x').unwrap();
+                        let monitor = self.active.iter_mut().find(|m| m.name == name).unwrap();
+                        monitor.position = Some((x.parse().unwrap(), y.parse().unwrap()));
+                    }
+                    "--primary" => {
+                        for monitor in &mut self.active {
+                            monitor.primary = monitor.name == name;
+                        }
+                    }
+                    "--rate" => {
+                        args.next();
+                    }
+                    other => panic!("SimulatedXrandr doesn't understand {}", other),
+                }
+            }
+        }
+        fn listactivemonitors(&self) -> String {
+            let mut output = format!("Monitors: {}", self.active.len());
+            for (i, monitor) in self.active.iter().enumerate() {
+                let (x, y) = monitor.position.unwrap_or((0, 0));
+                output.push_str(&format!(
+                    "\n {}: +{}{} {}/1x{}/1+{}+{}  {}",
+                    i,
+                    if monitor.primary { "*" } else { "" },
+                    monitor.name,
+                    monitor.resolution.width,
+                    monitor.resolution.height,
+                    x,
+                    y,
+                    monitor.name
+                ));
+            }
+            output
+        }
+    }
+
+    impl Runner for SimulatedXrandr {
+        fn xrandr(&mut self, args: &[String]) -> Result<String> {
+            if args == ["--listactivemonitors"] {
+                return Ok(self.listactivemonitors());
+            }
+            if args == ["-q"] {
+                return Ok(self.query.clone().unwrap_or_else(|| OUTPUT.to_string()));
+            }
+            self.applied += 1;
+            if !self.ignores_changes {
+                self.apply(args);
+            }
+            Ok(String::new())
+        }
+        fn command(&mut self, _: &[String]) -> Result<i32> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_biggest_selection_is_idempotent() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*eDP-1 1920/301x1200/188+0+0  eDP-1",
+        );
+        let plan = monitors.single_on_plan(&monitors.largest().unwrap().name);
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert!(plan.needs_change(&current));
+        xrandr.xrandr(&plan.command_string()).unwrap();
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.monitors.len(), 1);
+        assert_eq!(current.monitors[0].name, "HDMI-1-0");
+        assert!(!plan.needs_change(&current));
+    }
+
+    /// A lid that stays however it was set, open by default.
+    #[derive(Default)]
+    struct FakeLid {
+        closed: bool,
+    }
+
+    impl LidSource for FakeLid {
+        fn is_closed(&mut self) -> Result<bool> {
+            Ok(self.closed)
+        }
+    }
+
+    /// Only moves when slept on.
+    struct FakeClock {
+        now: Instant,
     }
 
+    impl Default for FakeClock {
+        fn default() -> FakeClock {
+            FakeClock {
+                now: Instant::now(),
+            }
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.now
+        }
+        fn sleep(&mut self, duration: Duration) {
+            self.now += duration;
+        }
+    }
+
+    #[cfg(feature = "listen")]
     #[test]
-    fn test_monitor_parse() {
+    fn test_listen_applies_plans() {
+        use std::io::Write;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir().join(format!("not-shit-randr-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client
+            .write_all(
+                br#"{"outputs": [{"name": "HDMI-1-0", "mode": [2560, 1440], "primary": true}, {"name": "eDP-1"}]}
+not json
+{"outputs": [{"name": "eDP-1", "mode": [1920, 1200], "rotate": "left"}]}
+"#,
+            )
+            .unwrap();
+        drop(client);
+        let mut runner = FakeRunner::default();
+        let (stream, _) = listener.accept().unwrap();
+        listen::handle_connection(stream, &mut runner, &[]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            runner.calls,
+            vec![
+                vec![
+                    "xrandr",
+                    "--output",
+                    "HDMI-1-0",
+                    "--mode",
+                    "2560x1440",
+                    "--primary",
+                    "--output",
+                    "eDP-1",
+                    "--off"
+                ],
+                vec![
+                    "xrandr",
+                    "--output",
+                    "eDP-1",
+                    "--mode",
+                    "1920x1200",
+                    "--rotate",
+                    "left"
+                ],
+            ]
+        );
+    }
+
+    /// Stops the listener once something has been applied.
+    #[cfg(feature = "listen")]
+    struct StopAfterApply {
+        runner: FakeRunner,
+        terminate: Terminate,
+    }
+
+    #[cfg(feature = "listen")]
+    impl Runner for StopAfterApply {
+        fn xrandr(&mut self, args: &[String]) -> Result<String> {
+            self.terminate.request();
+            self.runner.xrandr(args)
+        }
+        fn command(&mut self, command: &[String]) -> Result<i32> {
+            self.runner.command(command)
+        }
+    }
+
+    #[cfg(feature = "listen")]
+    #[test]
+    fn test_listen_survives_bad_client() {
+        use std::io::Write;
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-bad-client-{}.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || {
+            let connect = || loop {
+                if let Ok(stream) = UnixStream::connect(&client_path) {
+                    return stream;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            };
+            connect().write_all(b"\xff\xfe\n").unwrap();
+            connect()
+                .write_all(br#"{"outputs": [{"name": "eDP-1", "mode": [1920, 1200]}]}"#)
+                .unwrap();
+        });
+        let terminate = Terminate::default();
+        let mut runner = StopAfterApply {
+            runner: FakeRunner::default(),
+            terminate: terminate.clone(),
+        };
+        listen::serve(
+            &path,
+            &mut runner,
+            &mut FakeClock::default(),
+            &[],
+            &terminate,
+        )
+        .unwrap();
+        client.join().unwrap();
+        assert_eq!(
+            runner.runner.calls,
+            vec![vec!["xrandr", "--output", "eDP-1", "--mode", "1920x1200"]]
+        );
+    }
+
+    #[cfg(feature = "listen")]
+    #[test]
+    fn test_listen_drops_silent_client() {
+        use std::io::Write;
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-silent-client-{}.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let client_path = path.clone();
+        let (done, finished) = std::sync::mpsc::channel::<()>();
+        let client = std::thread::spawn(move || {
+            let connect = || loop {
+                if let Ok(stream) = UnixStream::connect(&client_path) {
+                    return stream;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            };
+            // Stays connected without a word until the listener is done, or gives up.
+            let silent = connect();
+            connect()
+                .write_all(br#"{"outputs": [{"name": "eDP-1", "mode": [1920, 1200]}]}"#)
+                .unwrap();
+            let _ = finished.recv_timeout(Duration::from_secs(10));
+            drop(silent);
+        });
+        let terminate = Terminate::default();
+        let mut runner = StopAfterApply {
+            runner: FakeRunner::default(),
+            terminate: terminate.clone(),
+        };
+        let start = Instant::now();
+        listen::serve(
+            &path,
+            &mut runner,
+            &mut FakeClock::default(),
+            &[],
+            &terminate,
+        )
+        .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        done.send(()).unwrap();
+        client.join().unwrap();
+        assert_eq!(runner.runner.calls.len(), 1);
+    }
+
+    #[cfg(feature = "listen")]
+    #[test]
+    fn test_listen_keeps_other_files() {
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-not-a-socket-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "disabled_outputs = []").unwrap();
+        let terminate = Terminate::default();
+        terminate.request();
+        assert!(listen::serve(
+            &path,
+            &mut FakeRunner::default(),
+            &mut FakeClock::default(),
+            &[],
+            &terminate,
+        )
+        .is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "disabled_outputs = []"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "listen")]
+    #[test]
+    fn test_listen_keeps_disabled_outputs_off() {
+        use std::io::Write;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-disabled-{}.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client
+            .write_all(
+                br#"{"outputs": [{"name": "HDMI-1-0", "mode": [2560, 1440], "primary": true}, {"name": "eDP-1", "mode": [1920, 1200]}]}
+"#,
+            )
+            .unwrap();
+        drop(client);
+        let mut runner = FakeRunner::default();
+        let (stream, _) = listener.accept().unwrap();
+        listen::handle_connection(stream, &mut runner, &["HDMI-1-0".to_string()]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            runner.calls,
+            vec![vec![
+                "xrandr",
+                "--output",
+                "HDMI-1-0",
+                "--off",
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--primary"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_verify() {
         let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
-        assert_eq!(monitors.monitors.len(), 2);
-        assert_eq!(monitors.largest().name, "HDMI-1-0");
-        assert_eq!(monitors.largest().width, 2560);
-        assert_eq!(monitors.largest().height, 1440);
+        let plan = monitors.single_on_plan("eDP-1");
+        let before = "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0";
+        let mut xrandr = SimulatedXrandr::with_active(before);
+        apply_plan(&mut xrandr, &plan, true).unwrap();
+        let mut xrandr = SimulatedXrandr {
+            ignores_changes: true,
+            ..SimulatedXrandr::with_active(before)
+        };
+        apply_plan(&mut xrandr, &plan, false).unwrap();
+        let error = apply_plan(&mut xrandr, &plan, true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "xrandr didn't apply the layout, the active outputs are now HDMI-1-0"
+        );
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let options =
+            Options::parse(["--dry-run", "--config", "/nonexistent/config.toml"].map(String::from))
+                .unwrap();
+        assert!(options.print_command);
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*eDP-1 1920/301x1200/188+0+0  eDP-1",
+        );
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+    }
+
+    #[test]
+    fn test_compare_current_json() {
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-baseline-{}.json",
+            std::process::id()
+        ));
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        let live = Monitors::from_query(OUTPUT.trim()).unwrap().summary();
+        std::fs::write(&path, serde_json::to_string(&live).unwrap()).unwrap();
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            compare_current_json: Some(path.clone()),
+            ..Options::default()
+        };
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        std::fs::write(
+            &path,
+            r#"[{"name":"HDMI-1-0","resolution":"1920x1080","rate":60.0,"primary":true},{"name":"eDP-1","resolution":"1920x1200","rate":60.1,"primary":false}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            1
+        );
+        let baseline: Vec<OutputSummary> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            diff_summaries(&baseline, &live),
+            vec![
+                "HDMI-1-0: resolution 1920x1080 is now 2560x1440",
+                "HDMI-1-0: rate 60.00 is now 59.95",
+                "HDMI-1-0: is no longer primary",
+                "eDP-1: active in the baseline but not now"
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_restore_on_term() {
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        let possible = query_possible_monitors(&mut xrandr, false).unwrap();
+        let initial = possible.restore_plan(&query_current_monitors(&mut xrandr).unwrap());
+        xrandr
+            .xrandr(&possible.single_on_plan("eDP-1").command_string())
+            .unwrap();
+        assert_eq!(
+            query_current_monitors(&mut xrandr).unwrap().names(),
+            vec!["eDP-1"]
+        );
+        let mut runner = FakeRunner::default();
+        restore_layout(&mut runner, &initial).unwrap();
+        assert_eq!(
+            runner.calls,
+            vec![vec![
+                "xrandr",
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--pos",
+                "0x0",
+                "--primary"
+            ]]
+        );
+        restore_layout(&mut xrandr, &initial).unwrap();
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["HDMI-1-0"]);
+        assert!(current.monitors[0].primary);
+    }
+
+    #[test]
+    fn test_cycle_resolution() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("not-shit-randr-cycle-{}", std::process::id()))
+            .join("cycle");
+        let _ = std::fs::remove_file(&path);
+        let modes = (0..hdmi.modes.len() + 1)
+            .map(|_| {
+                cycle_resolution(hdmi, &path).unwrap().outputs[0]
+                    .mode
+                    .unwrap()
+            })
+            .collect::<Vec<(usize, usize)>>();
+        // 2560x1440 is current, so cycling starts on the next mode down.
+        assert_eq!(modes[0], hdmi.modes[1].resolution.into());
+        assert_eq!(modes[1], hdmi.modes[2].resolution.into());
+        assert_eq!(modes[hdmi.modes.len() - 1], (2560, 1440));
+        assert_eq!(modes[hdmi.modes.len()], modes[0]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_settle_timeout() {
+        // The sample has one output driving pixels, this reports two forever.
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 2
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0
+ 1: +eDP-1 1920/301x1200/188+2560+0  eDP-1",
+        );
+        let mut clock = FakeClock::default();
+        let start = clock.now();
+        let (possible, current) =
+            query_settled_monitors(&mut xrandr, &mut clock, false, Duration::from_millis(450))
+                .unwrap();
+        assert!(possible.active_count_warning(&current).is_some());
+        assert_eq!(clock.now() - start, Duration::from_millis(500));
+
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        let mut clock = FakeClock::default();
+        let start = clock.now();
+        query_settled_monitors(&mut xrandr, &mut clock, false, DEFAULT_SETTLE_TIMEOUT).unwrap();
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn test_run_backend() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            arrangement: "extend".parse().unwrap(),
+            ..Options::default()
+        };
+        let mut backend = XrandrBackend(SimulatedXrandr::with_active("Monitors: 0"));
+        assert_eq!(
+            run_backend(&options, &mut backend, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut backend.0).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1", "HDMI-1-0"]);
+        assert_eq!(current.find("HDMI-1-0").unwrap().position, Some((1920, 0)));
+    }
+
+    #[test]
+    fn test_run_backend_never_applies_read_only_modes() {
+        let modes = [
+            Options {
+                print_command: true,
+                ..Options::default()
+            },
+            Options {
+                list: true,
+                ..Options::default()
+            },
+            Options {
+                json: true,
+                ..Options::default()
+            },
+            Options {
+                nagios: true,
+                ..Options::default()
+            },
+            Options {
+                health_check: true,
+                ..Options::default()
+            },
+        ];
+        for options in modes {
+            let options = Options {
+                config: Some("/nonexistent/config.toml".into()),
+                ..options
+            };
+            let mut backend = XrandrBackend(SimulatedXrandr::with_active("Monitors: 0"));
+            run_backend(&options, &mut backend, &mut FakeLid::default()).unwrap();
+            assert_eq!(backend.0.applied, 0);
+        }
+    }
+
+    #[test]
+    fn test_run_backend_rejects_unsupported_options() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            watch: true,
+            ..Options::default()
+        };
+        let mut backend = XrandrBackend(SimulatedXrandr::with_active("Monitors: 0"));
+        let error = run_backend(&options, &mut backend, &mut FakeLid::default()).unwrap_err();
+        assert!(error.to_string().contains("--watch"));
+        assert_eq!(backend.0.applied, 0);
+    }
+
+    #[test]
+    fn test_max_resolution_single_monitor() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            max_resolution: Some((1920, 1080)),
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        xrandr.query = Some(OUTPUT.replace("eDP-1 connected primary", "eDP-1 disconnected"));
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        let hdmi = current.find("HDMI-1-0").unwrap();
+        assert_eq!(hdmi.resolution, Resolution::new(1920, 1080));
+    }
+
+    #[test]
+    fn test_closed_lid() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            ..Options::default()
+        };
+        // The laptop panel is the largest, but shut.
+        let query = OUTPUT
+            .replace("HDMI-1-0 connected 2560x1440+0+0", "HDMI-1-0 connected")
+            .replace("   2560x1440     59.95*+\n", "")
+            .replace("   2048x1080     60.00\n", "")
+            .replace("   1920x1200     59.88\n", "");
+        let active = "Monitors: 1
+ 0: +*eDP-1 1920/301x1200/188+0+0  eDP-1";
+        let mut xrandr = SimulatedXrandr::with_active(active);
+        xrandr.query = Some(query.clone());
+        run(
+            &options,
+            &mut xrandr,
+            &mut FakeClock::default(),
+            &mut FakeLid::default(),
+        )
+        .unwrap();
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        let mut xrandr = SimulatedXrandr::with_active(active);
+        xrandr.query = Some(query);
+        run(
+            &options,
+            &mut xrandr,
+            &mut FakeClock::default(),
+            &mut FakeLid { closed: true },
+        )
+        .unwrap();
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["HDMI-1-0"]);
+    }
+
+    #[test]
+    fn test_select_target_filters() {
+        let possible = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let current = Monitors::from_listactivemonitors("Monitors: 0").unwrap();
+        let prefixes = DEFAULT_INTERNAL_PREFIXES.map(String::from).to_vec();
+        let select = |options: &Options, config: &Config| {
+            select_target(
+                options,
+                config,
+                &prefixes,
+                &possible,
+                &current,
+                &mut FakeLid::default(),
+            )
+            .unwrap()
+            .map(|m| m.name.clone())
+        };
+        let config = Config::default();
+        assert_eq!(
+            select(&Options::default(), &config).as_deref(),
+            Some("HDMI-1-0")
+        );
+        let options = Options {
+            min_area: 4_000_000,
+            ..Options::default()
+        };
+        assert_eq!(select(&options, &config), None);
+        let options = Options {
+            select_by_name_regex: Some(regex::Regex::new("^eDP").unwrap()),
+            ..Options::default()
+        };
+        assert_eq!(select(&options, &config).as_deref(), Some("eDP-1"));
+        let config = Config {
+            disabled_outputs: vec!["HDMI-1-0".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            select(&Options::default(), &config).as_deref(),
+            Some("eDP-1")
+        );
+        let config = Config {
+            preferred_primary: vec!["eDP-1".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            select(&Options::default(), &config).as_deref(),
+            Some("eDP-1")
+        );
+    }
+
+    #[test]
+    fn test_list_without_monitors() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            list: true,
+            ..Options::default()
+        };
+        let mut runner = FakeRunner {
+            output: OUTPUT.replace(" connected", " disconnected"),
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(
+                &options,
+                &mut runner,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            1
+        );
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_no_op_runs() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            ..Options::default()
+        };
+        let mut clock = FakeClock::default();
+        // Already on the largest monitor.
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        assert_eq!(
+            run(&options, &mut xrandr, &mut clock, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        assert_eq!(xrandr.applied, 0);
+        // Nothing active yet, the largest is turned on.
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(&options, &mut xrandr, &mut clock, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        assert_eq!(xrandr.applied, 1);
+        // A single connected monitor is left alone.
+        let mut runner = FakeRunner {
+            output: OUTPUT.replace("eDP-1 connected primary", "eDP-1 disconnected"),
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(&options, &mut runner, &mut clock, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        assert_eq!(runner.calls, vec![vec!["xrandr", "-q"]]);
+    }
+
+    #[test]
+    fn test_config_preferred_primary() {
+        let path =
+            std::env::temp_dir().join(format!("not-shit-randr-config-{}.toml", std::process::id()));
+        std::fs::write(&path, r#"preferred_primary = ["DP-1", "eDP-1"]"#).unwrap();
+        let options = Options {
+            config: Some(path.clone()),
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_config_disabled_outputs() {
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-disabled-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            r#"disabled_outputs = ["HDMI-1-0"]
+
+[profiles.desk]
+HDMI-1-0 = { mode = "2560x1440", primary = true }
+eDP-1 = { mode = "1920x1200" }"#,
+        )
+        .unwrap();
+        let mut options = Options {
+            config: Some(path.clone()),
+            ..Options::default()
+        };
+        // The largest output is normally picked, disabled it's passed over.
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        // Nor do the other modes.
+        let mut backend = XrandrBackend(SimulatedXrandr::with_active("Monitors: 0"));
+        let backend_options = Options {
+            config: Some(path.clone()),
+            ..Options::default()
+        };
+        assert_eq!(
+            run_backend(&backend_options, &mut backend, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut backend.0).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        let nagios = Options {
+            config: Some(path.clone()),
+            nagios: true,
+            ..Options::default()
+        };
+        // HDMI-1-0 is the only one active in the sample, the rest is dark.
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(
+                &nagios,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            1
+        );
+        // Profiles don't turn it back on either.
+        options.profile = Some("desk".to_string());
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_disabled_outputs_stay_off_when_restoring() {
+        let path = std::env::temp_dir().join(format!(
+            "not-shit-randr-disabled-restore-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, r#"disabled_outputs = ["HDMI-1-0"]"#).unwrap();
+        let both = "Monitors: 2
+ 0: +*eDP-1 1920/301x1200/188+0+0  eDP-1
+ 1: +HDMI-1-0 2560/597x1440/336+1920+0  HDMI-1-0";
+        let options = Options {
+            config: Some(path.clone()),
+            restore_on_exit: vec!["true".to_string()],
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active(both);
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(
+            query_current_monitors(&mut xrandr).unwrap().names(),
+            vec!["eDP-1"]
+        );
+        let options = Options {
+            config: Some(path.clone()),
+            restore_on_term: true,
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active(both);
+        let terminate = Terminate::default();
+        terminate.request();
+        watch(
+            &options,
+            &mut xrandr,
+            &mut FakeClock::default(),
+            &mut FakeLid::default(),
+            &mut ModifiedTime::new(path.clone()),
+            &terminate,
+        )
+        .unwrap();
+        assert_eq!(
+            query_current_monitors(&mut xrandr).unwrap().names(),
+            vec!["eDP-1"]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_screenlayout_keeps_disabled_outputs_off() {
+        let dir = std::env::temp_dir().join(format!(
+            "not-shit-randr-screenlayout-disabled-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(&config, r#"disabled_outputs = ["HDMI-1-0"]"#).unwrap();
+        let script = dir.join("docked.sh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nxrandr --output eDP-1 --off --output HDMI-1-0 --mode 2560x1440\n",
+        )
+        .unwrap();
+        let options = Options {
+            config: Some(config),
+            run_screenlayout: Some(script.clone()),
+            ..Options::default()
+        };
+        let mut runner = FakeRunner::default();
+        assert!(run(
+            &options,
+            &mut runner,
+            &mut FakeClock::default(),
+            &mut FakeLid::default()
+        )
+        .is_err());
+        assert!(runner.calls.is_empty());
+        std::fs::write(&script, "#!/bin/sh\nxrandr --output HDMI-1-0 --off\n").unwrap();
+        assert_eq!(
+            run(
+                &options,
+                &mut runner,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(runner.calls.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_monitors() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let config = Config {
+            disabled_outputs: vec!["eDP-1".to_string()],
+            ..Config::default()
+        };
+        let options = Options::default();
+        assert_eq!(
+            json_monitors(&options, &Config::default(), &monitors).names(),
+            vec!["eDP-1", "HDMI-1-0"]
+        );
+        assert_eq!(
+            json_monitors(&options, &config, &monitors).names(),
+            vec!["HDMI-1-0"]
+        );
+        let options = Options {
+            active_only: true,
+            ..Options::default()
+        };
+        // Only HDMI-1-0 is driven in the sample.
+        assert_eq!(
+            json_monitors(&options, &Config::default(), &monitors).names(),
+            vec!["HDMI-1-0"]
+        );
+    }
+
+    #[test]
+    fn test_primary_must_support() {
+        let options = Options::parse([
+            "--config".to_string(),
+            "/nonexistent/config.toml".to_string(),
+            "--primary-must-support".to_string(),
+            "1400x1050".to_string(),
+        ])
+        .unwrap();
+        // HDMI-1-0 is bigger but only the laptop panel offers 1400x1050.
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        let options = Options {
+            primary_must_support: Some((7680, 4320)),
+            ..options
+        };
+        let error = run(
+            &options,
+            &mut xrandr,
+            &mut FakeClock::default(),
+            &mut FakeLid::default(),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("supports 7680x4320"));
+    }
+
+    #[test]
+    fn test_watch_restores_on_term() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            restore_on_term: true,
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*eDP-1 1920/302x1200/189+0+0  eDP-1",
+        );
+        let terminate = Terminate::default();
+        terminate.request();
+        assert_eq!(
+            watch(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default(),
+                &mut ModifiedTime::new("/nonexistent/config.toml".into()),
+                &terminate,
+            )
+            .unwrap(),
+            0
+        );
+        // The first run moved to HDMI-1-0, stopping put eDP-1 back.
+        assert_eq!(xrandr.applied, 2);
+        assert_eq!(
+            query_current_monitors(&mut xrandr).unwrap().names(),
+            vec!["eDP-1"]
+        );
+    }
+
+    /// Stops the watch on its first poll without reporting a change.
+    struct StopAfterPoll(Terminate);
+
+    impl ChangeSource for StopAfterPoll {
+        fn changed(&mut self) -> bool {
+            self.0.request();
+            false
+        }
+    }
+
+    #[test]
+    fn test_force_first() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            ..Options::default()
+        };
+        // Already on the largest monitor, only the forced first run applies it again.
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        let terminate = Terminate::default();
+        assert_eq!(
+            watch(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default(),
+                &mut StopAfterPoll(terminate.clone()),
+                &terminate,
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(xrandr.applied, 1);
+        assert_eq!(
+            run(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default()
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(xrandr.applied, 1);
+        let options = Options::parse(["--force-first".to_string()]).unwrap();
+        assert!(options.force_first);
+    }
+
+    /// Rewrites the config on its first poll, then stops the watch on its second.
+    struct RewrittenConfig {
+        path: std::path::PathBuf,
+        polls: usize,
+        terminate: Terminate,
+    }
+
+    impl ChangeSource for RewrittenConfig {
+        fn changed(&mut self) -> bool {
+            self.polls += 1;
+            if self.polls > 1 {
+                self.terminate.request();
+                return false;
+            }
+            std::fs::write(
+                &self.path,
+                r#"[layouts."HDMI-1-0,eDP-1"]
+eDP-1 = { mode = "1920x1200", primary = true }
+HDMI-1-0 = { off = true }"#,
+            )
+            .unwrap();
+            true
+        }
+    }
+
+    #[test]
+    fn test_watch_reloads_config() {
+        let path =
+            std::env::temp_dir().join(format!("not-shit-randr-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let options = Options {
+            config: Some(path.clone()),
+            ..Options::default()
+        };
+        let mut xrandr = SimulatedXrandr::with_active(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        );
+        let terminate = Terminate::default();
+        let mut config = RewrittenConfig {
+            path: path.clone(),
+            polls: 0,
+            terminate: terminate.clone(),
+        };
+        assert_eq!(
+            watch(
+                &options,
+                &mut xrandr,
+                &mut FakeClock::default(),
+                &mut FakeLid::default(),
+                &mut config,
+                &terminate
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(config.polls, 2);
+        assert_eq!(xrandr.applied, 2);
+        let current = query_current_monitors(&mut xrandr).unwrap();
+        assert_eq!(current.names(), vec!["eDP-1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scale_option() {
+        let scale = |factor: &str| Options::parse(["--scale".to_string(), factor.to_string()]);
+        assert_eq!(scale("1.5").unwrap().scale, Some(1.5));
+        assert!(scale("0").is_err());
+        assert!(scale("-2").is_err());
+        assert!(scale("NaN").is_err());
+        assert!(scale("big").is_err());
+    }
+
+    #[test]
+    fn test_health_check() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            health_check: true,
+            ..Options::default()
+        };
+        let mut clock = FakeClock::default();
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(&options, &mut xrandr, &mut clock, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        let mut runner = FakeRunner {
+            output: OUTPUT.replace(" connected", " disconnected"),
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(&options, &mut runner, &mut clock, &mut FakeLid::default()).unwrap(),
+            1
+        );
+        assert_eq!(runner.calls, vec![vec!["xrandr", "-q"]]);
+        let mut runner = FakeRunner {
+            xrandr_fails: true,
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(&options, &mut runner, &mut clock, &mut FakeLid::default()).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_nagios() {
+        let monitors = Monitors::from_query(OUTPUT.trim()).unwrap();
+        assert_eq!(
+            monitors.nagios_status(),
+            (
+                0,
+                "OK - 1 monitors active | active=1 connected=2".to_string()
+            )
+        );
+        let dark = Monitors::from_query(&OUTPUT.trim().replace("2560x1440+0+0 ", "")).unwrap();
+        assert_eq!(dark.nagios_status().0, 1);
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            nagios: true,
+            ..Options::default()
+        };
+        let mut clock = FakeClock::default();
+        let mut xrandr = SimulatedXrandr::with_active("Monitors: 0");
+        assert_eq!(
+            run(&options, &mut xrandr, &mut clock, &mut FakeLid::default()).unwrap(),
+            0
+        );
+        let output = OUTPUT.replace(" connected", " disconnected");
+        let disconnected = Monitors::from_query(output.trim()).unwrap();
+        assert_eq!(
+            disconnected.nagios_status(),
+            (
+                2,
+                "CRITICAL - no monitors | active=0 connected=0".to_string()
+            )
+        );
+        let mut runner = FakeRunner {
+            output,
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(&options, &mut runner, &mut clock, &mut FakeLid::default()).unwrap(),
+            2
+        );
+        let mut runner = FakeRunner {
+            xrandr_fails: true,
+            ..FakeRunner::default()
+        };
+        assert_eq!(
+            run(&options, &mut runner, &mut clock, &mut FakeLid::default()).unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_randr_error() {
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            ..Options::default()
+        };
+        let mut runner = FakeRunner {
+            output: OUTPUT.replace(" connected", " disconnected"),
+            ..FakeRunner::default()
+        };
+        let error = run(
+            &options,
+            &mut runner,
+            &mut FakeClock::default(),
+            &mut FakeLid::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RandrError>(),
+            Some(RandrError::NoActiveMonitors)
+        ));
+        let mut runner = FakeRunner {
+            output: "Monitors: 1\n 0: +*eDP-1 wide  eDP-1".to_string(),
+            ..FakeRunner::default()
+        };
+        let error = query_current_monitors(&mut runner).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RandrError>(),
+            Some(RandrError::Parse(_))
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Expect to get both width and height from split  0: +*eDP-1 wide  eDP-1"
+        );
+    }
+
+    #[test]
+    fn test_failed_query_stops_run() {
+        let mut runner = FakeRunner {
+            xrandr_fails: true,
+            ..FakeRunner::default()
+        };
+        let options = Options {
+            config: Some("/nonexistent/config.toml".into()),
+            ..Options::default()
+        };
+        let error = run(
+            &options,
+            &mut runner,
+            &mut FakeClock::default(),
+            &mut FakeLid::default(),
+        )
+        .unwrap_err();
+        assert_eq!(format!("{:#}", error), "Can't open display");
+        assert_eq!(runner.calls, vec![vec!["xrandr", "-q"]]);
     }
 }
@@ -0,0 +1,3349 @@
+use crate::config::{self, Layout};
+use crate::plan::{parse_mode, ModeRequest, OutputCommand, Rotation, XrandrCommand};
+use crate::provider::Provider;
+use crate::resolution::{self, Resolution};
+use crate::RandrError;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub struct Monitors {
+    pub monitors: Vec<Monitor>,
+    /// Framebuffer limits, only reported by `-q`.
+    pub screen: Option<ScreenInfo>,
+    /// Problems with the xrandr output that were skipped over while parsing.
+    pub warnings: Vec<String>,
+    /// Every output `-q` lists, connected or not, in its order.
+    pub output_names: Vec<String>,
+}
+
+impl Monitors {
+    pub fn from_query(xrandr_outputs: &str) -> Result<Monitors, RandrError> {
+        let mut alive_monitors = Vec::new();
+        let mut warnings = Vec::new();
+        let mut output_names = Vec::new();
+        let mut chunks = MonitorChunks::new(xrandr_outputs.trim().lines());
+        let screen = chunks
+            .next()
+            .and_then(|chunk| match ScreenInfo::parse(chunk[0]) {
+                Ok(screen) => Some(screen),
+                Err(e) => {
+                    warnings.push(format!("Warning: couldn't parse the Screen line: {:#}", e));
+                    None
+                }
+            });
+        for chunk in chunks {
+            if let Some(name) = chunk[0].split_whitespace().next() {
+                output_names.push(name.to_string());
+            }
+            // Remembered displays can show as connected without listing a single mode, rows
+            // that are there but don't parse still get the warning below.
+            if chunk[0].contains("disconnected") || chunk.len() == 1 {
+                continue;
+            }
+            match Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk) {
+                Ok(monitor) => {
+                    let Resolution { width, height } = monitor.resolution;
+                    if width.checked_mul(height).is_none() {
+                        warnings.push(format!(
+                            "Warning: {} reports a {} mode, too many pixels to count, treating it as the largest possible.",
+                            monitor.name, monitor.resolution
+                        ));
+                    }
+                    alive_monitors.push(monitor);
+                }
+                Err(e) => warnings.push(format!(
+                    "Warning: skipping output, failure during parsing out monitor details from {:?}: {:#}",
+                    chunk[0], e
+                )),
+            }
+        }
+        Ok(Monitors {
+            monitors: alive_monitors,
+            screen,
+            warnings,
+            output_names,
+        })
+    }
+    /// Under `--strict` any warning raised while parsing is an error instead.
+    pub fn check_strict(&self) -> Result<()> {
+        if !self.warnings.is_empty() {
+            bail!("Strict mode: {}", self.warnings.join("\n"));
+        }
+        Ok(())
+    }
+    /// The monitor with the most pixels, the higher refresh breaks ties between equal areas.
+    /// `None` only when there are no monitors at all.
+    pub fn largest(&self) -> Option<&Monitor> {
+        // Rates are compared in hundredths of a hertz, as precise as xrandr prints them.
+        self.pick_with(|m| {
+            (
+                m.area(),
+                m.refresh.map(|rate| (rate * 100.0).round() as u32),
+            )
+        })
+    }
+    /// `None` only when there are no monitors at all.
+    pub fn smallest(&self) -> Option<&Monitor> {
+        self.pick_with(|m| Reverse(m.area()))
+    }
+    /// The physically biggest monitor, pixel area decides between monitors of unknown or equal
+    /// size. `None` only when there are no monitors at all.
+    pub fn physically_largest(&self) -> Option<&Monitor> {
+        self.pick_with(|m| {
+            (
+                m.phys_mm
+                    .map_or(0, |(width, height)| width.saturating_mul(height)),
+                m.area(),
+            )
+        })
+    }
+    pub fn select(&self, strategy: Strategy) -> Option<&Monitor> {
+        match strategy {
+            Strategy::Largest => self.largest(),
+            Strategy::Smallest => self.smallest(),
+            Strategy::PhysicalSize => self.physically_largest(),
+        }
+    }
+    /// `picked`, unless the `current` primary is just as big. Switching between two equally good
+    /// monitors only churns the layout.
+    pub fn sticky<'a>(&'a self, picked: &'a Monitor, current: &str) -> &'a Monitor {
+        self.find(current)
+            .filter(|m| m.area() == picked.area())
+            .unwrap_or(picked)
+    }
+    /// The monitor `strategy` picks among those still visible, a laptop panel under a closed
+    /// lid is only picked when nothing else is connected.
+    pub fn select_with_lid(
+        &self,
+        strategy: Strategy,
+        lid_closed: bool,
+        internal_prefixes: &[String],
+    ) -> Option<&Monitor> {
+        if lid_closed {
+            let external = self.filtered(|m| !is_internal(&m.name, internal_prefixes));
+            if let Some(monitor) = external.select(strategy) {
+                return self.find(&monitor.name);
+            }
+        }
+        self.select(strategy)
+    }
+    /// The monitor with the greatest `key`, ties keep the first monitor listed. Every built in
+    /// selection strategy is a key over this.
+    ///
+    /// ```
+    /// # use not_shit_randr::Monitors;
+    /// let monitors = Monitors::from_query(
+    ///     "Screen 0: minimum 320 x 200, current 1920 x 1080, maximum 16384 x 16384
+    /// DP-1 connected
+    ///    1920x1080     60.00
+    /// HDMI-1 connected
+    ///    1280x1024     75.00",
+    /// )
+    /// .unwrap();
+    /// // The squarest monitor, for reading documents.
+    /// let squarest = monitors.pick_with(|m| {
+    ///     let size = m.resolution;
+    ///     std::cmp::Reverse(size.width * 1000 / size.height)
+    /// });
+    /// assert_eq!(squarest.unwrap().name, "HDMI-1");
+    /// ```
+    pub fn pick_with<K: Ord>(&self, key: impl Fn(&Monitor) -> K) -> Option<&Monitor> {
+        let mut picked: Option<(&Monitor, K)> = None;
+        for monitor in &self.monitors {
+            let monitor_key = key(monitor);
+            if picked.as_ref().is_none_or(|(_, best)| monitor_key > *best) {
+                picked = Some((monitor, monitor_key));
+            }
+        }
+        picked.map(|(monitor, _)| monitor)
+    }
+    /// The monitors matching `keep`, for narrowing down the candidates before selecting one.
+    pub fn filtered(&self, keep: impl Fn(&Monitor) -> bool) -> Monitors {
+        Monitors {
+            monitors: self.monitors.iter().filter(|m| keep(m)).cloned().collect(),
+            screen: self.screen,
+            warnings: Vec::new(),
+            output_names: self.output_names.clone(),
+        }
+    }
+    /// Only the monitors plugged into the provider `name`, one GPU in a PRIME setup.
+    pub fn on_provider(&self, providers: &[Provider], name: &str) -> Result<Monitors> {
+        let outputs = Provider::outputs_of(providers, name, &self.output_names)?;
+        Ok(self.filtered(|m| outputs.contains(&m.name)))
+    }
+    /// The monitors whose names match `pattern`, it's an error for none to.
+    pub fn matching(&self, pattern: &Regex) -> Result<Monitors> {
+        let matching = self.filtered(|m| pattern.is_match(&m.name));
+        if matching.monitors.is_empty() {
+            bail!(
+                "No connected monitor matches {}, connected are {}",
+                pattern,
+                self.names().join(", ")
+            );
+        }
+        Ok(matching)
+    }
+    pub fn find(&self, name: &str) -> Option<&Monitor> {
+        self.monitors.iter().find(|m| m.name == name)
+    }
+    /// First of `names`, in order of preference, that is currently connected.
+    pub fn pick_preferred(&self, names: &[String]) -> Option<&Monitor> {
+        names.iter().find_map(|name| self.find(name))
+    }
+    /// Plan with the named monitor on and primary, every other monitor off.
+    pub fn single_on_plan(&self, name: &str) -> XrandrCommand {
+        let mut plan = XrandrCommand {
+            outputs: self
+                .monitors
+                .iter()
+                .map(|m| m.output_command(m.name == name))
+                .collect(),
+        };
+        plan.ensure_single_primary();
+        plan
+    }
+    /// Plan enabling every monitor at its max mode side by side in the order they are listed,
+    /// with the named monitor as primary.
+    pub fn extend_plan(&self, primary: &str) -> XrandrCommand {
+        let outputs = self
+            .monitors
+            .iter()
+            .map(|monitor| {
+                let mut command = monitor.output_command(true);
+                command.primary = monitor.name == primary;
+                command
+            })
+            .collect();
+        let mut plan = XrandrCommand { outputs };
+        plan.place_side_by_side();
+        plan
+    }
+    /// Dispatches to the plan for `arrangement` around the selected monitor.
+    pub fn arranged_plan(&self, arrangement: Arrangement, primary: &str) -> XrandrCommand {
+        match arrangement {
+            Arrangement::Single => self.single_on_plan(primary),
+            Arrangement::Extend => self.extend_plan(primary),
+            Arrangement::Mirror => self.mirror_plan(primary),
+        }
+    }
+    /// Plan applying a stored layout, connected outputs the layout doesn't mention are turned off.
+    pub fn layout_plan(&self, layout: &Layout) -> Result<XrandrCommand> {
+        let mut outputs = Vec::new();
+        for monitor in &self.monitors {
+            let mut command = monitor.output_command(false);
+            if let Some(output) = layout.outputs.get(&monitor.name).filter(|o| !o.off) {
+                command.mode = Some(match &output.mode {
+                    Some(mode) => parse_mode(mode)?,
+                    None => monitor.resolution.into(),
+                });
+                command.rate = output.rate;
+                command.primary = output.primary;
+            }
+            outputs.push(command);
+        }
+        let mut plan = XrandrCommand { outputs };
+        plan.ensure_single_primary();
+        Ok(plan)
+    }
+    /// Plan enabling only the named monitors at their max mode, all others are turned off.
+    pub fn keep_only_plan(&self, names: &[String]) -> Result<XrandrCommand> {
+        for name in names {
+            if !self.monitors.iter().any(|m| &m.name == name) {
+                bail!("{} is not a connected output", name);
+            }
+        }
+        let mut plan = XrandrCommand {
+            outputs: self
+                .monitors
+                .iter()
+                .map(|m| m.output_command(names.contains(&m.name)))
+                .collect(),
+        };
+        plan.ensure_single_primary();
+        Ok(plan)
+    }
+    /// `-q` and `--listactivemonitors` can disagree while xrandr is mid transition.
+    pub fn active_count_warning(&self, current: &Monitors) -> Option<String> {
+        let active = self.monitors.iter().filter(|m| m.is_active()).count();
+        if active == current.monitors.len() {
+            return None;
+        }
+        Some(format!(
+            "Warning: xrandr -q reports {} active outputs but --listactivemonitors reports {}, the displays may be mid transition.",
+            active,
+            current.monitors.len()
+        ))
+    }
+    /// Plan setting every connected monitor to the same mode, monitors that don't list the
+    /// mode are left untouched and reported in the returned warnings.
+    pub fn force_mode_plan(&self, (width, height): (usize, usize)) -> (XrandrCommand, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut outputs = Vec::new();
+        for monitor in &self.monitors {
+            if !monitor.supports_mode(width, height) {
+                warnings.push(format!(
+                    "Warning: {} doesn't support {}x{}, leaving it unchanged.",
+                    monitor.name, width, height
+                ));
+                continue;
+            }
+            let mut command = monitor.output_command(true);
+            command.mode = Some((width, height));
+            outputs.push(command);
+        }
+        let mut plan = XrandrCommand { outputs };
+        plan.ensure_single_primary();
+        (plan, warnings)
+    }
+    /// Turns symbolic `--mode` values into sizes using each output's parsed modes.
+    pub fn resolve_modes(
+        &self,
+        requests: &[(String, ModeRequest)],
+    ) -> Result<Vec<(String, (usize, usize))>> {
+        requests
+            .iter()
+            .map(|(name, request)| {
+                let monitor = self
+                    .find(name)
+                    .context(format!("--mode given for {} which is not connected", name))?;
+                let mode = match request {
+                    ModeRequest::Size(width, height) => (*width, *height),
+                    ModeRequest::Max => monitor.resolution.into(),
+                    ModeRequest::Preferred => monitor
+                        .preferred_mode()
+                        .map(|mode| mode.resolution.into())
+                        .context(format!("{} does not report a preferred mode", name))?,
+                };
+                Ok((name.clone(), mode))
+            })
+            .collect()
+    }
+    /// Moves every enabled output to its mode closest to `target`.
+    pub fn target_modes(&self, plan: &mut XrandrCommand, target: (usize, usize)) {
+        for output in plan.outputs.iter_mut().filter(|o| o.mode.is_some()) {
+            if let Some(mode) = self
+                .find(&output.name)
+                .and_then(|monitor| monitor.closest_mode(target))
+            {
+                output.mode = Some(mode.resolution.into());
+                output.rate = None;
+            }
+        }
+    }
+    /// Moves every enabled output to its widest mode `height` pixels tall, outputs without one
+    /// are left alone and reported in the returned warnings.
+    pub fn height_modes(&self, plan: &mut XrandrCommand, height: usize) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for output in plan.outputs.iter_mut().filter(|o| o.mode.is_some()) {
+            let Some(monitor) = self.find(&output.name) else {
+                continue;
+            };
+            match monitor.widest_mode_with_height(height) {
+                Some(mode) => {
+                    output.mode = Some(mode.resolution.into());
+                    output.rate = None;
+                }
+                None => warnings.push(format!(
+                    "Warning: {} has no mode {} pixels tall, leaving it as it is.",
+                    output.name, height
+                )),
+            }
+        }
+        warnings
+    }
+    /// Moves every enabled output to its mode with the highest refresh rate, however small.
+    pub fn fastest_modes(&self, plan: &mut XrandrCommand) {
+        for output in plan.outputs.iter_mut().filter(|o| o.mode.is_some()) {
+            if let Some(mode) = self
+                .find(&output.name)
+                .and_then(|monitor| monitor.fastest_mode())
+            {
+                output.mode = Some(mode.resolution.into());
+                output.rate = None;
+            }
+        }
+    }
+    /// Drops every enabled output that is bigger than `max` down to its best mode within it.
+    /// Outputs with nothing small enough are left alone and reported in the returned warnings.
+    pub fn cap_modes(
+        &self,
+        plan: &mut XrandrCommand,
+        (max_width, max_height): (usize, usize),
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for output in &mut plan.outputs {
+            let Some((width, height)) = output.mode else {
+                continue;
+            };
+            if width <= max_width && height <= max_height {
+                continue;
+            }
+            let Some(monitor) = self.find(&output.name) else {
+                continue;
+            };
+            match monitor.best_mode_within(max_width, max_height) {
+                Some(mode) => {
+                    output.mode = Some(mode.resolution.into());
+                    output.rate = None;
+                }
+                None => warnings.push(format!(
+                    "Warning: {} has no mode within {}x{}, leaving it at {}x{}.",
+                    output.name, max_width, max_height, width, height
+                )),
+            }
+        }
+        warnings
+    }
+    /// Heads up for enabled outputs on DVI or HDMI whose mode needs more pixel clock than a
+    /// single link comfortably carries, those tend to fail on long or cheap cables.
+    pub fn bandwidth_warnings(&self, plan: &XrandrCommand) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for output in &plan.outputs {
+            let Some((width, height)) = output.mode else {
+                continue;
+            };
+            let Some(limit) = SINGLE_LINK_LIMITS_MHZ
+                .iter()
+                .find(|(prefix, _)| output.name.starts_with(prefix))
+                .map(|(_, limit)| *limit)
+            else {
+                continue;
+            };
+            // xrandr picks the first rate listed when the plan doesn't set one.
+            let Some(rate) = output.rate.or_else(|| {
+                self.find(&output.name)?
+                    .find_mode(width, height)?
+                    .refresh
+                    .first()
+                    .copied()
+            }) else {
+                continue;
+            };
+            let clock = self
+                .find(&output.name)
+                .and_then(|m| m.find_mode(width, height)?.pixel_clock(rate))
+                .map_or_else(|| pixel_clock_mhz(width, height, rate), f64::from);
+            if clock > limit {
+                warnings.push(format!(
+                    "Warning: {}x{} at {:.2}Hz on {} needs roughly {:.0}MHz of pixel clock, more than the {:.0}MHz a single link carries reliably. It may fail on long or cheap cables.",
+                    width, height, rate, output.name, clock, limit
+                ));
+            }
+        }
+        warnings
+    }
+    /// Sets each enabled output without a rate to the highest its mode offers, xrandr would
+    /// otherwise go with whichever rate it lists first.
+    pub fn set_highest_rates(&self, plan: &mut XrandrCommand) {
+        for output in &mut plan.outputs {
+            let Some((width, height)) = output.mode else {
+                continue;
+            };
+            if output.rate.is_none() {
+                output.rate = self
+                    .find(&output.name)
+                    .and_then(|m| m.highest_rate(width, height));
+            }
+        }
+    }
+    /// Pins each named output to its refresh rate, which the mode the plan has for that output
+    /// has to offer. Another mode of the same monitor offering it isn't enough.
+    pub fn set_rates(&self, plan: &mut XrandrCommand, rates: &[(String, f32)]) -> Result<()> {
+        for (name, rate) in rates {
+            let output = plan
+                .outputs
+                .iter_mut()
+                .find(|o| &o.name == name)
+                .context(format!("--rate given for {} which is not connected", name))?;
+            let (width, height) = output.mode.context(format!(
+                "--rate given for {} which is not being turned on",
+                name
+            ))?;
+            let listed = self
+                .find(name)
+                .and_then(|m| m.find_mode(width, height))
+                .and_then(|mode| {
+                    mode.refresh
+                        .iter()
+                        .copied()
+                        .find(|listed| (listed - rate).abs() < RATE_TOLERANCE)
+                })
+                .context(format!(
+                    "{} doesn't offer {}Hz at {}x{}",
+                    name, rate, width, height
+                ))?;
+            output.rate = Some(listed);
+        }
+        Ok(())
+    }
+    /// Switches each enabled output to the fractional variant of its refresh rate when offered.
+    pub fn prefer_fractional_rates(&self, plan: &mut XrandrCommand) {
+        for output in &mut plan.outputs {
+            let Some((width, height)) = output.mode else {
+                continue;
+            };
+            let rate = self
+                .monitors
+                .iter()
+                .find(|m| m.name == output.name)
+                .and_then(|m| m.find_mode(width, height))
+                .and_then(Mode::fractional_rate);
+            if rate.is_some() {
+                output.rate = rate;
+            }
+        }
+    }
+    /// Plan mirroring `primary` onto every other connected monitor, all at the largest mode
+    /// every monitor supports.
+    pub fn clone_plan(&self, primary: &str) -> Result<XrandrCommand> {
+        let source = self
+            .find(primary)
+            .context(format!("{} is not a connected output", primary))?;
+        let shared = source
+            .modes
+            .iter()
+            .filter(|mode| {
+                self.monitors
+                    .iter()
+                    .all(|m| m.supports_mode(mode.resolution.width, mode.resolution.height))
+            })
+            .max_by_key(|mode| mode.area())
+            .context(format!(
+                "No mode is supported by every output cloning {}",
+                primary
+            ))?;
+        Ok(self.mirror_at(primary, |_| shared.resolution.into()))
+    }
+    /// Like `clone_plan`, but when no mode is shared every other output mirrors the primary
+    /// with `--auto --same-as`, leaving xrandr to pick its mode. The primary keeps its own.
+    pub fn mirror_plan(&self, primary: &str) -> XrandrCommand {
+        self.clone_plan(primary).unwrap_or_else(|_| {
+            let mut plan = self.mirror_at(primary, |m| m.resolution.into());
+            for output in plan.outputs.iter_mut().filter(|o| !o.primary) {
+                output.auto = true;
+            }
+            plan
+        })
+    }
+    fn mirror_at(&self, primary: &str, mode: impl Fn(&Monitor) -> (usize, usize)) -> XrandrCommand {
+        let outputs = self
+            .monitors
+            .iter()
+            .map(|monitor| {
+                let mut command = monitor.output_command(true);
+                command.mode = Some(mode(monitor));
+                command.primary = monitor.name == primary;
+                if !command.primary {
+                    command.same_as = Some(primary.to_string());
+                }
+                command
+            })
+            .collect();
+        XrandrCommand { outputs }
+    }
+    /// Plan putting the connected monitors back into the `current` active state.
+    pub fn restore_plan(&self, current: &Monitors) -> XrandrCommand {
+        XrandrCommand {
+            outputs: self
+                .monitors
+                .iter()
+                .map(|monitor| {
+                    let active = current.monitors.iter().find(|m| m.name == monitor.name);
+                    OutputCommand {
+                        name: monitor.name.clone(),
+                        mode: active.map(|m| m.resolution.into()),
+                        rate: None,
+                        position: active.and_then(|m| m.position),
+                        same_as: None,
+                        rotate: None,
+                        panning: None,
+                        scale: None,
+                        properties: Vec::new(),
+                        auto: false,
+                        primary: active.is_some_and(|m| m.primary),
+                    }
+                })
+                .collect(),
+        }
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.monitors.iter().map(|m| m.name.clone()).collect()
+    }
+    /// One `NAME DPI` line per monitor, for picking scaling settings.
+    pub fn ppi_report(&self) -> Vec<String> {
+        self.monitors
+            .iter()
+            .map(|m| match m.dpi() {
+                Some(dpi) => format!("{} {:.1}", m.name, dpi),
+                None => format!("{} unknown", m.name),
+            })
+            .collect()
+    }
+    /// One `NAME WIDTHxHEIGHT@REFRESH [primary] [selected]` line per connected monitor for
+    /// `--list`, in `-q` order. Active monitors show their current mode, the others their
+    /// largest. The `selected` monitor is marked.
+    pub fn list(&self, active_only: bool, selected: Option<&str>) -> Vec<String> {
+        let mut lines = Vec::new();
+        for monitor in &self.monitors {
+            if active_only && !monitor.is_active() {
+                continue;
+            }
+            let (resolution, rate) = match monitor.current_mode() {
+                Some(mode) if monitor.is_active() => (mode.resolution, mode.current_rate),
+                _ => (monitor.resolution, monitor.refresh),
+            };
+            let mut line = format!("{} {}", monitor.name, resolution);
+            if let Some(rate) = rate {
+                line.push_str(&format!("@{:.2}", rate));
+            }
+            if monitor.primary {
+                line.push_str(" primary");
+            }
+            if selected == Some(monitor.name.as_str()) {
+                line.push_str(" selected");
+            }
+            lines.push(line);
+        }
+        lines
+    }
+    /// Every connected monitor followed by its modes, for `--list-modes`.
+    pub fn list_modes(&self, dedupe: bool, active_only: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        for monitor in self
+            .monitors
+            .iter()
+            .filter(|m| !active_only || m.is_active())
+        {
+            lines.push(monitor.name.clone());
+            let mut modes: Vec<(Resolution, Vec<f32>)> = Vec::new();
+            for mode in &monitor.modes {
+                match modes
+                    .iter_mut()
+                    .find(|(resolution, _)| dedupe && *resolution == mode.resolution)
+                {
+                    Some((_, rates)) => rates.extend(&mode.refresh),
+                    None => modes.push((mode.resolution, mode.refresh.clone())),
+                }
+            }
+            for (resolution, mut rates) in modes {
+                if dedupe {
+                    dedupe_rates(&mut rates);
+                }
+                let rates = rates
+                    .iter()
+                    .map(|rate| format!("{:.2}", rate))
+                    .collect::<Vec<String>>();
+                lines.push(
+                    format!("   {}  {}", resolution, rates.join(" "))
+                        .trim_end()
+                        .to_string(),
+                );
+            }
+        }
+        lines
+    }
+    /// The outputs driving pixels right now, for `--summary-json`.
+    pub fn summary(&self) -> Vec<OutputSummary> {
+        self.monitors
+            .iter()
+            .filter(|m| m.is_active())
+            .map(|m| {
+                let mode = m.current_mode();
+                OutputSummary {
+                    name: m.name.clone(),
+                    resolution: mode
+                        .map_or(m.resolution, |mode| mode.resolution)
+                        .to_string(),
+                    rate: mode.and_then(|mode| mode.current_rate),
+                    primary: m.primary,
+                }
+            })
+            .collect()
+    }
+    /// Every connected monitor with the one the selection picks, for `--json`.
+    pub fn report<'a>(&'a self, selected: Option<&'a str>) -> MonitorsReport<'a> {
+        MonitorsReport {
+            monitors: &self.monitors,
+            selected,
+        }
+    }
+    /// The raw `-q` output followed by everything parsed out of it, or why parsing failed,
+    /// to paste into a bug report.
+    pub fn debug_model(xrandr_outputs: &str) -> String {
+        let model = match Monitors::from_query(xrandr_outputs.trim()) {
+            Ok(monitors) => format!("{:#?}", monitors),
+            Err(e) => format!("Parsing failed: {:#}", e),
+        };
+        format!(
+            "--- xrandr -q ---\n{}\n--- parsed ---\n{}",
+            xrandr_outputs.trim_end(),
+            model
+        )
+    }
+    /// A monitoring plugin status line and its exit code: OK while some monitor is driving
+    /// pixels, WARNING when monitors are connected but all dark and CRITICAL with none at all.
+    pub fn nagios_status(&self) -> (i32, String) {
+        let connected = self.monitors.len();
+        let active = self.monitors.iter().filter(|m| m.is_active()).count();
+        let perfdata = format!("active={} connected={}", active, connected);
+        if connected == 0 {
+            (2, format!("CRITICAL - no monitors | {}", perfdata))
+        } else if active == 0 {
+            (
+                1,
+                format!(
+                    "WARNING - {} monitors connected but none active | {}",
+                    connected, perfdata
+                ),
+            )
+        } else {
+            (0, format!("OK - {} monitors active | {}", active, perfdata))
+        }
+    }
+    /// Adds the pixel clocks `xrandr --verbose` lists to the matching modes, matched by size
+    /// and rate. Modes only `--verbose` lists are left out rather than duplicated.
+    pub fn merge_verbose(&mut self, verbose: &str) {
+        for (name, (width, height), clock, rate) in parse_verbose_modes(verbose) {
+            let Some(monitor) = self.monitors.iter_mut().find(|m| m.name == name) else {
+                continue;
+            };
+            let Some((mode, rate)) = monitor
+                .modes
+                .iter_mut()
+                .filter(|mode| mode.resolution.width == width && mode.resolution.height == height)
+                .find_map(|mode| {
+                    let listed = mode
+                        .refresh
+                        .iter()
+                        .copied()
+                        .find(|listed| (listed - rate).abs() < RATE_TOLERANCE)?;
+                    Some((mode, listed))
+                })
+            else {
+                continue;
+            };
+            if mode.pixel_clock(rate).is_none() {
+                mode.pixel_clocks.push((rate, clock));
+            }
+        }
+    }
+    /// The key a config layout needs to apply to exactly these monitors.
+    pub fn signature(&self) -> String {
+        config::signature(&self.names())
+    }
+    /// xrandr should only ever report one primary, but a malformed state can mark several.
+    pub fn duplicate_primary_warning(&self, primary: &str) -> Option<String> {
+        let primaries = self
+            .monitors
+            .iter()
+            .filter(|m| m.primary)
+            .map(|m| m.name.as_str())
+            .collect::<Vec<&str>>();
+        if primaries.len() < 2 {
+            return None;
+        }
+        Some(format!(
+            "Warning: multiple outputs marked primary ({}), only {} will be set as primary.",
+            primaries.join(", "),
+            primary
+        ))
+    }
+    /// Parses `wlr-randr` output, an unindented line naming each output followed by indented
+    /// `Key: value` properties and a `Modes:` list such as `1920x1080 px, 60.000000 Hz (current)`.
+    pub fn from_wlr_text(wlr_randr: &str) -> Result<Monitors, RandrError> {
+        let mut monitors = Vec::new();
+        let mut output_names = Vec::new();
+        for chunk in MonitorChunks::new(wlr_randr.trim().lines()) {
+            let monitor = Monitor::parse_wlr_chunk(&chunk)?;
+            output_names.push(monitor.name.clone());
+            monitors.push(monitor);
+        }
+        Ok(Monitors {
+            monitors,
+            screen: None,
+            warnings: Vec::new(),
+            output_names,
+        })
+    }
+    pub fn from_listactivemonitors(listactivemonitors: &str) -> Result<Monitors, RandrError> {
+        let mut monitors = Vec::new();
+        // Odd captures can leave a `\r` or spaces after the name, which would end up in
+        // `--output` arguments.
+        for line in listactivemonitors.lines().skip(1).map(str::trim_end) {
+            let error = |message: &str| RandrError::Parse(format!("{} {}", message, line));
+            let mut line_iter = line.split_whitespace();
+            let name = line_iter
+                .next_back()
+                .ok_or_else(|| error("Expected to parse name. Found no whitespace"))?;
+            let width_height = line_iter
+                .next_back()
+                .ok_or_else(|| error("Expect mode after name"))?;
+            let (width, height) = width_height
+                .split_once(resolution::SEPARATORS)
+                .ok_or_else(|| error("Expect to get both width and height from split"))?;
+            let (width, width_mm) = width.split_once('/').unwrap_or((width, ""));
+            let height = height.split('+').next().unwrap_or(height);
+            let (height, height_mm) = height.split_once('/').unwrap_or((height, ""));
+            let width = width
+                .parse()
+                .map_err(|_| error("Width should be an integer"))?;
+            let height = height
+                .parse()
+                .map_err(|_| error("Height should be an integer"))?;
+            monitors.push(Monitor {
+                name: name.into(),
+                resolution: Resolution::new(width, height),
+                refresh: None,
+                modes: Vec::new(),
+                primary: line.contains('*'),
+                position: parse_position(width_height),
+                phys_mm: width_mm.parse().ok().zip(height_mm.parse().ok()),
+                rotation: Rotation::Normal,
+                state: State::Connected,
+            })
+        }
+        Ok(Monitors {
+            monitors,
+            screen: None,
+            warnings: Vec::new(),
+            output_names: Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Monitor {
+    /// Size of the preferred mode.
+    #[serde(flatten)]
+    pub resolution: Resolution,
+    /// Rate of the preferred mode, `None` when parsed from `--listactivemonitors`.
+    pub refresh: Option<f32>,
+    pub name: String,
+    /// Every mode listed for the output, empty when parsed from `--listactivemonitors`.
+    pub modes: Vec<Mode>,
+    pub primary: bool,
+    /// Offset of the current mode, `None` when the output isn't active.
+    pub position: Option<(i32, i32)>,
+    /// Physical width and height in millimetres, when xrandr knows them.
+    pub phys_mm: Option<(usize, usize)>,
+    /// Current rotation, only reported by `-q`.
+    pub rotation: Rotation,
+    pub state: State,
+}
+
+/// How sure xrandr is that something is plugged into an output. Disconnected outputs aren't
+/// parsed into monitors at all.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    #[default]
+    Connected,
+    /// `unknown connection`, reported by some VGA and KVM setups that can't detect a display.
+    Unknown,
+}
+
+impl Monitor {
+    /// Saturates rather than overflowing on nonsense sizes, see `Monitors::from_query`.
+    pub fn area(&self) -> usize {
+        self.resolution.area()
+    }
+    pub fn output_command(&self, on: bool) -> OutputCommand {
+        OutputCommand {
+            name: self.name.clone(),
+            mode: on.then_some(self.resolution.into()),
+            rate: None,
+            position: None,
+            same_as: None,
+            rotate: None,
+            panning: None,
+            scale: None,
+            properties: Vec::new(),
+            auto: false,
+            primary: false,
+        }
+    }
+    /// `--setmonitor` arguments adding a monitor called `label` covering the whole output, so
+    /// scripts can find it by role rather than by connector.
+    pub fn label_command(&self, label: &str) -> Vec<String> {
+        vec![
+            "--setmonitor".to_string(),
+            label.to_string(),
+            "auto".to_string(),
+            self.name.clone(),
+        ]
+    }
+    /// `--setmonitor` arguments dividing the active output into `parts` equal virtual monitors
+    /// from left to right, only the first keeps the output attached.
+    pub fn split_command(&self, parts: usize) -> Result<Vec<String>> {
+        if parts == 0 || !self.resolution.width.is_multiple_of(parts) {
+            bail!(
+                "Can't split the {} wide {} into {} equal parts",
+                self.resolution.width,
+                self.name,
+                parts
+            );
+        }
+        let (x, y) = self
+            .position
+            .context(format!("Can't split {}, it isn't active", self.name))?;
+        let (width_mm, height_mm) = self.phys_mm.unwrap_or((0, 0));
+        let width = self.resolution.width / parts;
+        let mut args = Vec::new();
+        for part in 0..parts {
+            args.push("--setmonitor".to_string());
+            args.push(format!("{}~{}", self.name, part + 1));
+            args.push(format!(
+                "{}/{}x{}/{}+{}+{}",
+                width,
+                width_mm / parts,
+                self.resolution.height,
+                height_mm,
+                x + (width * part) as i32,
+                y
+            ));
+            args.push(if part == 0 {
+                self.name.clone()
+            } else {
+                "none".to_string()
+            });
+        }
+        Ok(args)
+    }
+    pub fn parse_monitor_from_listactivemonitors_chunk(
+        chunk: &[impl AsRef<str>],
+    ) -> Result<Monitor, RandrError> {
+        let header = chunk[0].as_ref();
+        // The connector name is usually followed by its state, but not always.
+        let name = header.split_whitespace().next().ok_or_else(|| {
+            RandrError::Parse(format!("Splitting line for name failed: {}", header))
+        })?;
+        // Some drivers leave blank lines among the mode rows.
+        let mode_rows = chunk[1..]
+            .iter()
+            .map(|line| line.as_ref())
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<&str>>();
+        let modes = mode_rows
+            .iter()
+            .filter_map(|line| Mode::parse(line))
+            .collect::<Vec<Mode>>();
+        // The mode the display asks for, which isn't always listed first. Without one the
+        // largest mode stands in, ties going to the first listed.
+        let best = modes
+            .iter()
+            .find(|mode| mode.preferred_rate.is_some())
+            .or_else(|| modes.iter().rev().max_by_key(|mode| mode.area()))
+            .ok_or_else(|| {
+                RandrError::Parse(format!(
+                    "Expect resolution to be widthxheight: {}",
+                    mode_rows
+                        .first()
+                        .map_or("no modes listed", |row| row.trim())
+                ))
+            })?;
+        let refresh = best.preferred_rate.or(best.refresh.first().copied());
+        Ok(Monitor {
+            name: String::from(name),
+            resolution: best.resolution,
+            refresh,
+            modes,
+            primary: header.split_whitespace().any(|token| token == "primary"),
+            position: header
+                .split_whitespace()
+                .find(|token| {
+                    token.starts_with(|c: char| c.is_ascii_digit()) && token.contains('+')
+                })
+                .and_then(parse_position),
+            phys_mm: parse_phys_mm(header),
+            // The current rotation follows the geometry, before the list of supported ones.
+            rotation: header
+                .split('(')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .find_map(|token| token.parse().ok())
+                .unwrap_or_default(),
+            state: if header.contains("unknown connection") {
+                State::Unknown
+            } else {
+                State::Connected
+            },
+        })
+    }
+    fn parse_wlr_chunk(chunk: &[&str]) -> Result<Monitor, RandrError> {
+        let name = chunk[0].split_whitespace().next().ok_or_else(|| {
+            RandrError::Parse(format!("Splitting line for name failed: {}", chunk[0]))
+        })?;
+        let mut modes = Vec::new();
+        let mut enabled = true;
+        let mut position = None;
+        let mut phys_mm = None;
+        let mut rotation = Rotation::Normal;
+        for line in chunk[1..].iter().map(|line| line.trim()) {
+            if let Some(mode) = Mode::parse_wlr(line) {
+                modes.push(mode);
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "Enabled" => enabled = value == "yes",
+                "Position" => {
+                    position = value
+                        .split_once(',')
+                        .and_then(|(x, y)| x.parse().ok().zip(y.parse().ok()));
+                }
+                "Physical size" => {
+                    phys_mm = value
+                        .trim_end_matches("mm")
+                        .trim()
+                        .split_once(resolution::SEPARATORS)
+                        .and_then(|(w, h)| w.parse().ok().zip(h.parse().ok()))
+                        .filter(|&(w, h)| w > 0 && h > 0);
+                }
+                // Transforms turn counter-clockwise, flipped ones are left as they are.
+                "Transform" => {
+                    rotation = match value {
+                        "90" => Rotation::Left,
+                        "180" => Rotation::Inverted,
+                        "270" => Rotation::Right,
+                        _ => Rotation::Normal,
+                    };
+                }
+                _ => {}
+            }
+        }
+        let best = modes
+            .iter()
+            .find(|mode| mode.preferred_rate.is_some())
+            .or_else(|| modes.iter().rev().max_by_key(|mode| mode.area()))
+            .ok_or_else(|| RandrError::Parse(format!("Expect {} to list its modes", name)))?;
+        Ok(Monitor {
+            name: name.to_string(),
+            resolution: best.resolution,
+            refresh: best.refresh.first().copied(),
+            primary: false,
+            position: position.filter(|_| enabled),
+            phys_mm,
+            rotation,
+            modes,
+            state: State::Connected,
+        })
+    }
+    pub fn supports_mode(&self, width: usize, height: usize) -> bool {
+        self.find_mode(width, height).is_some()
+    }
+    /// The mode with the most pixels that fits within `max_width` by `max_height`.
+    pub fn best_mode_within(&self, max_width: usize, max_height: usize) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .filter(|mode| {
+                mode.resolution.width <= max_width && mode.resolution.height <= max_height
+            })
+            // Reversed so ties go to the mode xrandr lists first.
+            .rev()
+            .max_by_key(|mode| mode.area())
+    }
+    /// The widest mode exactly `height` pixels tall, e.g. 1920x1080 over 1440x1080 for 1080p.
+    pub fn widest_mode_with_height(&self, height: usize) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .filter(|mode| mode.resolution.height == height)
+            .rev()
+            .max_by_key(|mode| mode.resolution.width)
+    }
+    /// The mode nearest `target` by squared distance between the sizes, ties going to the
+    /// mode xrandr lists first.
+    pub fn closest_mode(&self, (width, height): (usize, usize)) -> Option<&Mode> {
+        self.modes.iter().rev().min_by_key(|mode| {
+            mode.resolution
+                .width
+                .abs_diff(width)
+                .saturating_pow(2)
+                .saturating_add(mode.resolution.height.abs_diff(height).saturating_pow(2))
+        })
+    }
+    /// The mode offering the highest refresh rate, the largest of those tied. Interlaced and
+    /// doublescan modes are passed over since their rates count fields rather than frames.
+    pub fn fastest_mode(&self) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .filter(|mode| !mode.interlaced && !mode.doublescan)
+            .filter_map(|mode| Some((mode, mode.refresh.iter().copied().reduce(f32::max)?)))
+            .max_by(|(a, a_rate), (b, b_rate)| {
+                a_rate
+                    .total_cmp(b_rate)
+                    .then(a.resolution.cmp(&b.resolution))
+            })
+            .map(|(mode, _)| mode)
+    }
+    /// The highest rate across every progressive mode row listing this resolution, see
+    /// `fastest_mode` for why interlaced and doublescan rows don't count.
+    pub fn highest_rate(&self, width: usize, height: usize) -> Option<f32> {
+        self.modes
+            .iter()
+            .filter(|mode| mode.resolution.width == width && mode.resolution.height == height)
+            .filter(|mode| !mode.interlaced && !mode.doublescan)
+            .flat_map(|mode| mode.refresh.iter().copied())
+            .reduce(f32::max)
+    }
+    pub fn current_mode(&self) -> Option<&Mode> {
+        self.modes.iter().find(|mode| mode.current_rate.is_some())
+    }
+    pub fn preferred_mode(&self) -> Option<&Mode> {
+        self.modes.iter().find(|mode| mode.preferred_rate.is_some())
+    }
+    pub fn find_mode(&self, width: usize, height: usize) -> Option<&Mode> {
+        self.modes
+            .iter()
+            .find(|mode| mode.resolution.width == width && mode.resolution.height == height)
+    }
+    /// Pixels per inch along the diagonal of the max mode.
+    pub fn dpi(&self) -> Option<f64> {
+        let (width_mm, height_mm) = self.phys_mm?;
+        let diagonal_px = (self.resolution.width as f64).hypot(self.resolution.height as f64);
+        let diagonal_in = (width_mm as f64).hypot(height_mm as f64) / 25.4;
+        Some(diagonal_px / diagonal_in)
+    }
+    /// Outputs report a `WxH+X+Y` geometry in `-q` only while they are driving pixels.
+    pub fn is_active(&self) -> bool {
+        self.position.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Mode {
+    #[serde(flatten)]
+    pub resolution: Resolution,
+    /// Every refresh rate offered at this resolution, in the order xrandr lists them.
+    pub refresh: Vec<f32>,
+    pub interlaced: bool,
+    pub doublescan: bool,
+    /// The rate marked with `+`, only set on the mode the display itself asks for.
+    pub preferred_rate: Option<f32>,
+    /// The rate marked with `*`, only set on the mode the output is driving right now.
+    pub current_rate: Option<f32>,
+    /// `(rate, MHz)` pixel clocks of the rates `--verbose` details, empty from `-q` alone.
+    pub pixel_clocks: Vec<(f32, f32)>,
+}
+
+impl Mode {
+    pub fn area(&self) -> usize {
+        self.resolution.area()
+    }
+    /// Parses a mode row such as `1920x1080     60.00*+  59.94    50.00`.
+    pub fn parse(line: &str) -> Option<Mode> {
+        let mut tokens = line.split_whitespace();
+        let resolution = tokens.next()?;
+        let mut interlaced = resolution.ends_with('i');
+        let mut doublescan = false;
+        let mut preferred_rate = None;
+        let mut current_rate = None;
+        let resolution = parse_mode(resolution.trim_end_matches('i')).ok()?.into();
+        let mut refresh = Vec::new();
+        // Rare captures join the rates with commas, e.g. `60.00,59.94,50.00`.
+        for token in tokens.flat_map(|token| token.split(',').filter(|rate| !rate.is_empty())) {
+            let preferred = token.contains('+');
+            let current = token.contains('*');
+            // wlr-randr and some xrandr builds mark approximate rates, e.g. `~59.951`.
+            let token = token.trim_start_matches('~').trim_end_matches(['*', '+']);
+            // Timing flags are printed straight after the rate, e.g. `60.00d`.
+            let token = if let Some(rate) = token.strip_suffix('i') {
+                interlaced = true;
+                rate
+            } else if let Some(rate) = token.strip_suffix('d') {
+                doublescan = true;
+                rate
+            } else {
+                token
+            };
+            // xrandr leaves a space where an output isn't using the rate, so `60.10 +` marks
+            // the rate before it.
+            let rate = if token.is_empty() {
+                refresh.last().copied()
+            } else {
+                token.parse().ok().inspect(|rate| refresh.push(*rate))
+            };
+            if current {
+                current_rate = current_rate.or(rate);
+            }
+            if preferred {
+                preferred_rate = preferred_rate.or(rate);
+            }
+        }
+        Some(Mode {
+            resolution,
+            refresh,
+            interlaced,
+            doublescan,
+            preferred_rate,
+            current_rate,
+            pixel_clocks: Vec::new(),
+        })
+    }
+    /// Parses a `wlr-randr` mode row such as `1920x1080 px, 59.950001 Hz (preferred, current)`,
+    /// which only ever lists a single rate.
+    pub fn parse_wlr(line: &str) -> Option<Mode> {
+        let (size, rest) = line.split_once(" px, ")?;
+        let resolution = parse_mode(size.trim()).ok()?.into();
+        let (rate, flags) = rest.split_once(" Hz").unwrap_or((rest, ""));
+        let rate: f32 = rate.trim().parse().ok()?;
+        Some(Mode {
+            resolution,
+            refresh: vec![rate],
+            interlaced: false,
+            doublescan: false,
+            preferred_rate: flags.contains("preferred").then_some(rate),
+            current_rate: flags.contains("current").then_some(rate),
+            pixel_clocks: Vec::new(),
+        })
+    }
+    /// The pixel clock `--verbose` reported for `rate`, in MHz.
+    pub fn pixel_clock(&self, rate: f32) -> Option<f32> {
+        self.pixel_clocks
+            .iter()
+            .find(|(clocked, _)| (clocked - rate).abs() < RATE_TOLERANCE)
+            .map(|(_, clock)| *clock)
+    }
+    /// The NTSC style fractional rate, e.g. 59.94 for 60, when both it and its integer rate
+    /// are offered. The highest such pair wins.
+    pub fn fractional_rate(&self) -> Option<f32> {
+        self.refresh
+            .iter()
+            .filter(|rate| (*rate - rate.round()).abs() < 0.01)
+            .filter_map(|integer| {
+                let fractional = integer * 1000.0 / 1001.0;
+                self.refresh
+                    .iter()
+                    .copied()
+                    .find(|rate| (rate - fractional).abs() < 0.01)
+            })
+            .reduce(f32::max)
+    }
+}
+
+/// Parses the trailing `597mm x 336mm` of a `-q` header, projectors and virtual outputs
+/// report `0mm x 0mm` which is as good as unknown.
+fn parse_phys_mm(header: &str) -> Option<(usize, usize)> {
+    let (_, size) = header.rsplit_once(')')?;
+    let (width, height) = size.split_once(" x ")?;
+    let width = width.trim().strip_suffix("mm")?.parse().ok()?;
+    let height = height.trim().strip_suffix("mm")?.parse().ok()?;
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Parses the `+X+Y` offset out of a geometry such as `2560x1440+0+0`.
+///
+/// Scaled or transformed outputs can report fractional offsets like `+0.5+0`, xrandr only takes
+/// whole pixels for `--pos` so these are truncated.
+fn parse_position(geometry: &str) -> Option<(i32, i32)> {
+    let (_, offset) = geometry.split_once('+')?;
+    let (x, y) = offset.split_once('+')?;
+    let x: f64 = x.parse().ok()?;
+    let y: f64 = y.parse().ok()?;
+    Some((x.trunc() as i32, y.trunc() as i32))
+}
+
+/// One active output as printed by `--summary-json`, keep the fields stable for the status
+/// bar scripts reading them and the baselines `--compare-current-json` reads back.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputSummary {
+    pub name: String,
+    pub resolution: String,
+    pub rate: Option<f32>,
+    pub primary: bool,
+}
+
+/// The parsed monitors as printed by `--json`, `selected` is left out when nothing is connected.
+#[derive(Debug, Serialize)]
+pub struct MonitorsReport<'a> {
+    pub monitors: &'a [Monitor],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected: Option<&'a str>,
+}
+
+/// Every way the `live` summary differs from a saved `baseline`, one line per difference.
+pub fn diff_summaries(baseline: &[OutputSummary], live: &[OutputSummary]) -> Vec<String> {
+    let mut differences = Vec::new();
+    for expected in baseline {
+        let Some(actual) = live.iter().find(|o| o.name == expected.name) else {
+            differences.push(format!(
+                "{}: active in the baseline but not now",
+                expected.name
+            ));
+            continue;
+        };
+        if actual.resolution != expected.resolution {
+            differences.push(format!(
+                "{}: resolution {} is now {}",
+                expected.name, expected.resolution, actual.resolution
+            ));
+        }
+        if actual.rate != expected.rate {
+            let rate =
+                |rate: Option<f32>| rate.map_or("unknown".to_string(), |r| format!("{:.2}", r));
+            differences.push(format!(
+                "{}: rate {} is now {}",
+                expected.name,
+                rate(expected.rate),
+                rate(actual.rate)
+            ));
+        }
+        if actual.primary != expected.primary {
+            differences.push(format!(
+                "{}: {}",
+                expected.name,
+                if actual.primary {
+                    "is now primary"
+                } else {
+                    "is no longer primary"
+                }
+            ));
+        }
+    }
+    for actual in live
+        .iter()
+        .filter(|o| !baseline.iter().any(|b| b.name == o.name))
+    {
+        differences.push(format!(
+            "{}: active now but not in the baseline",
+            actual.name
+        ));
+    }
+    differences
+}
+
+/// Which monitor the automatic selection turns on.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    #[default]
+    Largest,
+    /// For picking out a small control panel among bigger monitors.
+    Smallest,
+    /// By the size in millimetres xrandr reports, for a big TV at a low resolution.
+    PhysicalSize,
+}
+
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(strategy: &str) -> Result<Strategy> {
+        match strategy {
+            "largest" | "biggest" => Ok(Strategy::Largest),
+            "smallest" => Ok(Strategy::Smallest),
+            "physical-size" => Ok(Strategy::PhysicalSize),
+            _ => bail!(
+                "Expected strategy to be largest, smallest or physical-size: {}",
+                strategy
+            ),
+        }
+    }
+}
+
+/// What happens to the monitors the automatic selection didn't pick.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Arrangement {
+    /// Turn them off.
+    #[default]
+    Single,
+    /// Keep them on, left to right in the order xrandr lists them.
+    Extend,
+    /// Show the selected monitor on all of them, at a mode they share when there is one.
+    Mirror,
+}
+
+impl FromStr for Arrangement {
+    type Err = anyhow::Error;
+
+    fn from_str(arrangement: &str) -> Result<Arrangement> {
+        match arrangement {
+            "single" => Ok(Arrangement::Single),
+            "extend" => Ok(Arrangement::Extend),
+            "mirror" => Ok(Arrangement::Mirror),
+            _ => bail!(
+                "Expected arrangement to be single, extend or mirror: {}",
+                arrangement
+            ),
+        }
+    }
+}
+
+/// Rough pixel clock ceilings of single link connections, DVI at 165MHz and HDMI 1.4 at 340MHz.
+const SINGLE_LINK_LIMITS_MHZ: [(&str, f64); 2] = [("DVI", 165.0), ("HDMI", 340.0)];
+
+/// Approximate pixel clock of a mode, with about 20% added for blanking intervals.
+fn pixel_clock_mhz(width: usize, height: usize, rate: f32) -> f64 {
+    width as f64 * height as f64 * rate as f64 * 1.2 / 1_000_000.0
+}
+
+/// Each mode `xrandr --verbose` details as its output, size, pixel clock in MHz and rate, from
+/// a `1920x1080 (0x48) 148.500MHz +HSync +VSync` row and the `v: ... clock 60.00Hz` row after.
+fn parse_verbose_modes(verbose: &str) -> Vec<(String, (usize, usize), f32, f32)> {
+    let mut modes = Vec::new();
+    let mut output = None;
+    let mut pending = None;
+    for line in verbose.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            output = line.split_whitespace().next();
+            pending = None;
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v:") => {
+                let rate = tokens
+                    .skip_while(|token| *token != "clock")
+                    .nth(1)
+                    .and_then(|rate| rate.strip_suffix("Hz")?.parse().ok());
+                if let (Some(output), Some((size, clock)), Some(rate)) =
+                    (output, pending.take(), rate)
+                {
+                    modes.push((output.to_string(), size, clock, rate));
+                }
+            }
+            Some(size) => {
+                let size = parse_mode(size.trim_end_matches('i')).ok();
+                let clock = tokens.find_map(|token| token.strip_suffix("MHz")?.parse::<f32>().ok());
+                if let Some(mode) = size.zip(clock) {
+                    pending = Some(mode);
+                }
+            }
+            None => {}
+        }
+    }
+    modes
+}
+
+/// Refresh rates closer than this are the same rate as far as a listing is concerned.
+const RATE_TOLERANCE: f32 = 0.05;
+
+/// Drops every rate within `RATE_TOLERANCE` of one earlier in the list.
+fn dedupe_rates(rates: &mut Vec<f32>) {
+    let mut kept: Vec<f32> = Vec::new();
+    for rate in rates.drain(..) {
+        if kept
+            .iter()
+            .all(|seen| (seen - rate).abs() >= RATE_TOLERANCE)
+        {
+            kept.push(rate);
+        }
+    }
+    *rates = kept;
+}
+
+/// Connector prefixes of laptop panels when neither the config nor the command line set them.
+pub const DEFAULT_INTERNAL_PREFIXES: [&str; 2] = ["eDP", "LVDS"];
+
+/// Laptop panels, as opposed to monitors plugged into the machine.
+pub fn is_internal(name: &str, prefixes: &[String]) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| name.starts_with(prefix.as_str()))
+}
+
+/// `--delmonitor` arguments removing every monitor in `xrandr --listmonitors` that isn't named
+/// after the one output it covers, the virtual monitors `--setmonitor` and `--split` create.
+pub fn delete_virtual_command(listmonitors: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for line in listmonitors.lines().skip(1) {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            bail!("Expect a name and geometry for each monitor: {}", line);
+        }
+        let name = fields[1].trim_start_matches(['+', '*']);
+        if fields[3..] != [name] {
+            args.push("--delmonitor".to_string());
+            args.push(name.to_string());
+        }
+    }
+    Ok(args)
+}
+
+/// The framebuffer sizes from the `Screen 0: minimum 320 x 200, current ...` line of `-q`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenInfo {
+    pub minimum: (usize, usize),
+    pub current: (usize, usize),
+    pub maximum: (usize, usize),
+}
+
+impl ScreenInfo {
+    /// Sizes are printed as both `2560 x 1440` and `2560x1440` depending on the build.
+    fn parse(line: &str) -> Result<ScreenInfo> {
+        let (_, sizes) = line
+            .split_once(':')
+            .context(format!("Expected Screen line to contain a ':' {}", line))?;
+        let mut screen = ScreenInfo {
+            minimum: (0, 0),
+            current: (0, 0),
+            maximum: (0, 0),
+        };
+        for size in sizes.split(',') {
+            let (name, size) = size
+                .trim()
+                .split_once(char::is_whitespace)
+                .context(format!("Expected a name before the size {}", size))?;
+            let size = parse_mode(&size.replace(char::is_whitespace, ""))?;
+            match name {
+                "minimum" => screen.minimum = size,
+                "current" => screen.current = size,
+                "maximum" => screen.maximum = size,
+                _ => {}
+            }
+        }
+        Ok(screen)
+    }
+}
+
+/// Streams `-q` output lines into one chunk per output, a header line followed by its
+/// indented mode lines, so only a single chunk is buffered at a time.
+struct MonitorChunks<I: Iterator> {
+    lines: Peekable<I>,
+}
+
+impl<I: Iterator> MonitorChunks<I> {
+    fn new(lines: I) -> MonitorChunks<I> {
+        MonitorChunks {
+            lines: lines.peekable(),
+        }
+    }
+}
+
+impl<I> Iterator for MonitorChunks<I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = vec![self.lines.next()?];
+        while let Some(line) = self.lines.next_if(|line| {
+            let line = line.as_ref();
+            line.trim().is_empty() || line.starts_with(char::is_whitespace)
+        }) {
+            chunk.push(line);
+        }
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use crate::edid;
+    use crate::orientation::OrientationSource;
+    use crate::plan::{shell_quote, ApplyOrder, Panning};
+    use std::path::Path;
+
+    const OUTPUT: &str = "
+Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384
+eDP-1 connected primary (normal left inverted right x axis y axis)
+   1920x1200     60.10 +  60.10    40.06
+   1920x1080     60.10
+   1600x1200     60.10
+   1680x1050     60.10
+   1400x1050     60.10
+   1600x900      60.10
+   1280x1024     60.10
+   1400x900      60.10
+   1280x960      60.10
+   1440x810      60.10
+   1368x768      60.10
+   1280x800      60.10
+   1280x720      60.10
+   1024x768      60.10
+   960x720       60.10
+   928x696       60.10
+   896x672       60.10
+   1024x576      60.10
+   960x600       60.10
+   960x540       60.10
+   800x600       60.10
+   840x525       60.10
+   864x486       60.10
+   700x525       60.10
+   800x450       60.10
+   640x512       60.10
+   700x450       60.10
+   640x480       60.10
+   720x405       60.09
+   684x384       60.10
+   640x360       60.09
+   512x384       60.10
+   512x288       60.09
+   480x270       60.09
+   400x300       60.10
+   432x243       60.09
+   320x240       60.10
+   360x202       60.09
+   320x180       60.09
+DP-1 disconnected (normal left inverted right x axis y axis)
+HDMI-1 disconnected (normal left inverted right x axis y axis)
+DP-2 disconnected (normal left inverted right x axis y axis)
+HDMI-2 disconnected (normal left inverted right x axis y axis)
+DP-3 disconnected (normal left inverted right x axis y axis)
+HDMI-3 disconnected (normal left inverted right x axis y axis)
+HDMI-4 disconnected (normal left inverted right x axis y axis)
+DP-1-0 disconnected (normal left inverted right x axis y axis)
+DP-1-1 disconnected (normal left inverted right x axis y axis)
+DP-1-2 disconnected (normal left inverted right x axis y axis)
+DP-1-3 disconnected (normal left inverted right x axis y axis)
+HDMI-1-0 connected 2560x1440+0+0 (normal left inverted right x axis y axis) 597mm x 336mm
+   2560x1440     59.95*+
+   2048x1080     60.00
+   1920x1200     59.88
+   1920x1080     60.00    59.94    50.00
+   1680x1050     59.95
+   1600x1200     60.00
+   1280x1024     75.02    60.02
+   1280x800      59.81
+   1280x720      59.94    50.00
+   1152x864      75.00
+   1024x768      75.03    60.00
+   800x600       75.00    60.32
+   720x576       50.00
+   720x480       59.94
+   640x480       75.00    59.94    59.93";
+
+    /// Every `tests/fixtures/NAME.txt` capture, with the monitor count from `NAME.expected`.
+    fn fixtures() -> Vec<(String, String, usize)> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures");
+        let mut fixtures = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .map(|path| {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let expected = std::fs::read_to_string(path.with_extension("expected"))
+                    .unwrap_or_else(|_| panic!("{} has no .expected file", name));
+                (
+                    name,
+                    std::fs::read_to_string(&path).unwrap(),
+                    expected.trim().parse().unwrap(),
+                )
+            })
+            .collect::<Vec<(String, String, usize)>>();
+        fixtures.sort();
+        fixtures
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let fixtures = fixtures();
+        assert!(fixtures
+            .iter()
+            .any(|(name, output, _)| name == "sample" && output.trim() == OUTPUT.trim()));
+        for (name, output, expected) in fixtures {
+            let monitors = Monitors::from_query(output.trim()).unwrap();
+            assert!(
+                monitors.warnings.is_empty(),
+                "{}: {:?}",
+                name,
+                monitors.warnings
+            );
+            assert_eq!(monitors.monitors.len(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let chunks = MonitorChunks::new(OUTPUT.trim().lines()).collect::<Vec<Vec<&str>>>();
+        let chunk_str = chunks.concat().join("\n");
+        assert_eq!(chunk_str.trim(), OUTPUT.trim());
+        // The Screen line, then exactly one chunk per output header.
+        let header = Regex::new(r"^\S+ (connected|disconnected)").unwrap();
+        let headers = OUTPUT.lines().filter(|line| header.is_match(line)).count();
+        assert_eq!(chunks.len(), headers + 1);
+        assert!(chunks[1..].iter().all(|chunk| header.is_match(chunk[0])));
+        // The last output keeps every one of its mode rows.
+        let last = chunks.last().unwrap();
+        assert!(last[0].starts_with("HDMI-1-0 connected"));
+        assert_eq!(last.last(), OUTPUT.trim().lines().last().as_ref());
+        assert_eq!(last.len(), 16);
+    }
+
+    #[test]
+    fn test_monitor_parse() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.monitors.len(), 2);
+        let largest = monitors.largest().unwrap();
+        assert_eq!(largest.name, "HDMI-1-0");
+        assert_eq!(largest.resolution.width, 2560);
+        assert_eq!(largest.resolution.height, 1440);
+    }
+
+    #[test]
+    fn test_full_mode_list() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        assert_eq!(hdmi.modes.len(), 15);
+        assert_eq!(hdmi.modes[0].resolution, Resolution::new(2560, 1440));
+        assert_eq!(hdmi.modes[0].refresh, vec![59.95]);
+        assert_eq!(hdmi.modes[0].current_rate, Some(59.95));
+        assert_eq!(hdmi.modes[0].preferred_rate, Some(59.95));
+        assert_eq!(hdmi.modes[3].refresh, vec![60.00, 59.94, 50.00]);
+        assert_eq!(hdmi.modes[14].refresh, vec![75.00, 59.94, 59.93]);
+        assert!(hdmi.supports_mode(1280, 720));
+        assert!(!hdmi.supports_mode(1366, 768));
+        // The chosen mode is still the preferred one.
+        assert_eq!(
+            (hdmi.resolution.width, hdmi.resolution.height, hdmi.refresh),
+            (2560, 1440, Some(59.95))
+        );
+    }
+
+    #[test]
+    fn test_target_modes() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        let closest = hdmi.closest_mode((1366, 768)).unwrap();
+        assert_eq!(closest.resolution, Resolution::new(1280, 800));
+        let closest = hdmi.closest_mode((1920, 1080)).unwrap();
+        assert_eq!(closest.resolution, Resolution::new(1920, 1080));
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        monitors.target_modes(&mut plan, (2000, 1100));
+        assert_eq!(plan.outputs[1].mode, Some((2048, 1080)));
+        assert_eq!(plan.outputs[0].mode, None);
+    }
+
+    #[test]
+    fn test_highest_rate() {
+        let chunk = [
+            "DVI-D-0 connected (normal left inverted right x axis y axis)",
+            "   1280x1024     60.02 +  75.02",
+            "   1024x768      75.03    60.00",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        assert_eq!(monitor.refresh, Some(60.02));
+        assert_eq!(monitor.highest_rate(1280, 1024), Some(75.02));
+        assert_eq!(monitor.highest_rate(640, 480), None);
+        let monitors = Monitors {
+            monitors: vec![monitor],
+            screen: None,
+            warnings: Vec::new(),
+            output_names: vec!["DVI-D-0".to_string()],
+        };
+        let mut plan = monitors.single_on_plan("DVI-D-0");
+        monitors.set_highest_rates(&mut plan);
+        assert_eq!(plan.outputs[0].rate, Some(75.02));
+        assert_eq!(plan.command_string()[4..6], ["--rate", "75.02"]);
+        let tv = Monitor::parse_monitor_from_listactivemonitors_chunk(&[
+            "HDMI-2 connected (normal left inverted right x axis y axis)",
+            "   1920x1080i    60.00",
+            "   1920x1080     30.00 +",
+        ])
+        .unwrap();
+        assert!(tv.modes[0].interlaced);
+        assert_eq!(tv.highest_rate(1920, 1080), Some(30.00));
+    }
+
+    #[test]
+    fn test_highest_rate_past_markers() {
+        let chunk = [
+            "HDMI-1 connected 1920x1080+0+0 (normal left inverted right x axis y axis)",
+            "   1920x1080     60.00*+  74.97    59.94    50.00",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        assert_eq!(monitor.modes[0].refresh, vec![60.00, 74.97, 59.94, 50.00]);
+        let monitors = Monitors {
+            monitors: vec![monitor],
+            screen: None,
+            warnings: Vec::new(),
+            output_names: vec!["HDMI-1".to_string()],
+        };
+        let mut plan = monitors.single_on_plan("HDMI-1");
+        monitors.set_highest_rates(&mut plan);
+        assert_eq!(plan.command_string()[4..6], ["--rate", "74.97"]);
+    }
+
+    #[test]
+    fn test_preferred_mode_not_first() {
+        let chunk = [
+            "DP-2 connected (normal left inverted right x axis y axis)",
+            "   3840x2160     30.00",
+            "   2560x1440     59.95 +  74.97",
+            "   1920x1080     60.00",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        assert_eq!(monitor.resolution, Resolution::new(2560, 1440));
+        assert_eq!(monitor.refresh, Some(59.95));
+        assert_eq!(monitor.modes[1].preferred_rate, Some(59.95));
+        // Without a preferred marker the largest mode is used, wherever it is listed.
+        let chunk = [
+            "DP-2 connected (normal left inverted right x axis y axis)",
+            "   1920x1080     60.00",
+            "   3840x2160     30.00    25.00",
+            "   2560x1440     59.95",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        assert_eq!(monitor.resolution, Resolution::new(3840, 2160));
+        assert_eq!(monitor.refresh, Some(30.00));
+    }
+
+    #[test]
+    fn test_header_without_space() {
+        let monitor =
+            Monitor::parse_monitor_from_listactivemonitors_chunk(&["eDP-1", "   1920x1200  60.00"])
+                .unwrap();
+        assert_eq!(monitor.name, "eDP-1");
+        assert_eq!(monitor.resolution, Resolution::new(1920, 1200));
+    }
+
+    #[test]
+    fn test_mode_without_rate() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   1920x1080\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert!(monitors.warnings.is_empty());
+        let dp = monitors.find("DP-2").unwrap();
+        assert_eq!(dp.resolution, Resolution::new(1920, 1080));
+        assert_eq!(dp.modes.len(), 1);
+        assert!(dp.modes[0].refresh.is_empty());
+    }
+
+    #[test]
+    fn test_blank_line_in_modes() {
+        let output = OUTPUT.replace("597mm x 336mm\n", "597mm x 336mm\n\n");
+        assert_ne!(output, OUTPUT);
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert!(monitors.warnings.is_empty());
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        assert_eq!(hdmi.resolution, Resolution::new(2560, 1440));
+        assert_eq!(hdmi.modes.len(), 15);
+    }
+
+    #[test]
+    fn test_duplicate_primary() {
+        let output = OUTPUT.replace("HDMI-1-0 connected", "HDMI-1-0 connected primary");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert!(monitors.monitors.iter().all(|m| m.primary));
+        assert!(monitors.duplicate_primary_warning("HDMI-1-0").is_some());
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        plan.skip_unchanged_primary(&monitors);
+        assert_eq!(
+            plan.command_string()
+                .iter()
+                .filter(|arg| *arg == "--primary")
+                .count(),
+            1
+        );
+        let command = monitors
+            .single_on_plan(&monitors.largest().unwrap().name)
+            .command_string();
+        assert_eq!(command.iter().filter(|arg| *arg == "--primary").count(), 1);
+        assert_eq!(
+            command,
+            vec![
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--primary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_connection() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "VGA-1 unknown connection (normal left inverted right x axis y axis)\n   3840x2160     30.00\nDP-2 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let vga = monitors.find("VGA-1").unwrap();
+        assert_eq!(vga.state, State::Unknown);
+        assert_eq!(monitors.find("HDMI-1-0").unwrap().state, State::Connected);
+        assert_eq!(monitors.largest().unwrap().name, "VGA-1");
+        let candidates = monitors.filtered(|m| m.state == State::Connected);
+        assert_eq!(candidates.largest().unwrap().name, "HDMI-1-0");
+    }
+
+    #[test]
+    fn test_min_area_excludes_phantom() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "VIRTUAL-1 connected\n   640x480       60.00\nDP-2 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.monitors.len(), 3);
+        let candidates = monitors.filtered(|m| m.area() > 640 * 480);
+        assert_eq!(candidates.monitors.len(), 2);
+        let target = candidates.largest().unwrap();
+        assert_eq!(target.name, "HDMI-1-0");
+        let only_phantom = Monitors::from_query(
+            "Screen 0: minimum 320 x 200, current 640 x 480, maximum 16384 x 16384
+VIRTUAL-1 connected
+   640x480       60.00",
+        )
+        .unwrap();
+        assert_eq!(only_phantom.largest().unwrap().name, "VIRTUAL-1");
+        assert!(only_phantom
+            .filtered(|m| m.area() >= 1024 * 768)
+            .largest()
+            .is_none());
+    }
+
+    #[test]
+    fn test_layout_plan() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let config = Config::from_toml(
+            r#"
+[layouts."eDP-1,HDMI-1-0"]
+eDP-1 = { mode = "1920x1080" }
+HDMI-1-0 = {}
+"#,
+        )
+        .unwrap();
+        let layout = config.match_layout(&monitors.names()).unwrap();
+        let plan = monitors.layout_plan(layout).unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1080",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--primary"
+            ]
+        );
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        assert!(plan.needs_change(&current));
+    }
+
+    #[test]
+    fn test_strip_profile_rates() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let config = Config::from_toml(
+            r#"
+[profiles.desk]
+eDP-1 = { off = true }
+HDMI-1-0 = { mode = "1920x1080", rate = 50.0 }
+"#,
+        )
+        .unwrap();
+        let mut plan = monitors
+            .layout_plan(config.profile("desk").unwrap())
+            .unwrap();
+        monitors.set_highest_rates(&mut plan);
+        assert!(plan
+            .command_string()
+            .windows(2)
+            .any(|pair| pair == ["--rate", "50.00"]));
+        plan.clear_rates();
+        assert!(!plan.command_string().contains(&"--rate".to_string()));
+    }
+
+    #[test]
+    fn test_connected_signature() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.signature(), "HDMI-1-0,eDP-1");
+    }
+
+    #[test]
+    fn test_keep_only() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   1920x1080     60.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.monitors.len(), 3);
+        let names = vec!["eDP-1".to_string(), "HDMI-1-0".to_string()];
+        let plan = monitors.keep_only_plan(&names).unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--output",
+                "DP-2",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--primary"
+            ]
+        );
+        assert!(monitors.keep_only_plan(&["DP-1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_active_count_mismatch() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert!(monitors.monitors[1].is_active());
+        assert!(!monitors.monitors[0].is_active());
+        let consistent = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        assert!(monitors.active_count_warning(&consistent).is_none());
+        let inconsistent = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0
+ 1: +eDP-1 1920/301x1200/188+2560+0  eDP-1",
+        )
+        .unwrap();
+        assert_eq!(inconsistent.monitors[1].position, Some((2560, 0)));
+        let warning = monitors.active_count_warning(&inconsistent).unwrap();
+        assert!(warning.contains("1 active outputs"));
+    }
+
+    #[test]
+    fn test_force_mode_on_all() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.monitors[0].modes.len(), 39);
+        let (plan, warnings) = monitors.force_mode_plan((1024, 768));
+        assert!(warnings.is_empty());
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1024x768",
+                "--primary",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "1024x768"
+            ]
+        );
+        let (plan, warnings) = monitors.force_mode_plan((2560, 1440));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(plan.outputs.len(), 1);
+        assert_eq!(plan.outputs[0].name, "HDMI-1-0");
+    }
+
+    #[test]
+    fn test_listactivemonitors_trailing_whitespace() {
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 2\r
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0 \r
+ 1: +eDP-1 1920/301x1200/188+2560+0  eDP-1\t ",
+        )
+        .unwrap();
+        assert_eq!(current.names(), vec!["HDMI-1-0", "eDP-1"]);
+        let plan = current.single_on_plan("HDMI-1-0");
+        assert_eq!(plan.command_string()[..2], ["--output", "HDMI-1-0"]);
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        // Every other mode row indented and spaced with tabs instead.
+        let tabs = OUTPUT
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match line.strip_prefix("   ") {
+                Some(row) if i % 2 == 0 => format!("\t{}", row.replace("    ", "\t")),
+                _ => line.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        assert!(tabs.contains("\n\t1920x1080\t"));
+        let spaces = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let tabs = Monitors::from_query(tabs.trim_end()).unwrap();
+        assert!(tabs.warnings.is_empty());
+        assert_eq!(tabs.names(), spaces.names());
+        for (tabbed, spaced) in tabs.monitors.iter().zip(&spaces.monitors) {
+            assert_eq!(tabbed.modes, spaced.modes);
+        }
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+\t0: +*HDMI-1-0\t2560/597x1440/336+0+0 \t HDMI-1-0",
+        )
+        .unwrap();
+        assert_eq!(current.names(), vec!["HDMI-1-0"]);
+        assert_eq!(current.monitors[0].resolution.width, 2560);
+    }
+
+    #[test]
+    fn test_multiplication_sign() {
+        let mode = Mode::parse("   1920×1080     60.00*+  59.94").unwrap();
+        assert_eq!(mode.resolution, Resolution::new(1920, 1080));
+        assert_eq!(mode.current_rate, Some(60.0));
+        let output = OUTPUT.replace("2560x1440", "2560×1440");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(
+            monitors.largest().unwrap().resolution.to_string(),
+            "2560x1440"
+        );
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597×1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        let hdmi = &current.monitors[0];
+        assert_eq!(hdmi.resolution, Resolution::new(2560, 1440));
+        assert_eq!(hdmi.phys_mm, Some((597, 336)));
+    }
+
+    #[test]
+    fn test_listactivemonitors_phys_mm() {
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0
+ 1: +VIRTUAL-1 1920x1080+2560+0  VIRTUAL-1",
+        )
+        .unwrap();
+        let hdmi = &current.monitors[0];
+        assert_eq!(hdmi.resolution, Resolution::new(2560, 1440));
+        assert_eq!(hdmi.phys_mm, Some((597, 336)));
+        assert_eq!(hdmi.position, Some((0, 0)));
+        let virtual_monitor = &current.monitors[1];
+        assert_eq!(
+            (
+                virtual_monitor.resolution.width,
+                virtual_monitor.resolution.height
+            ),
+            (1920, 1080)
+        );
+        assert_eq!(virtual_monitor.phys_mm, None);
+    }
+
+    #[test]
+    fn test_connected_without_modes() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected (normal left inverted right x axis y axis)",
+            "DP-2 connected (normal left inverted right x axis y axis)",
+        );
+        assert_ne!(output, OUTPUT);
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.names(), vec!["eDP-1", "HDMI-1-0"]);
+        assert!(monitors.warnings.is_empty());
+        assert!(monitors.output_names.contains(&"DP-2".to_string()));
+    }
+
+    #[test]
+    fn test_strict_malformed_chunk() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   garbage\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.monitors.len(), 2);
+        assert_eq!(monitors.warnings.len(), 1);
+        assert!(monitors.warnings[0].contains("DP-2"));
+        assert!(monitors.check_strict().is_err());
+        let clean = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert!(clean.check_strict().is_ok());
+    }
+
+    #[test]
+    fn test_prefer_fractional() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let hdmi = &monitors.monitors[1];
+        let mode = hdmi.find_mode(1920, 1080).unwrap();
+        assert_eq!(mode.refresh, vec![60.00, 59.94, 50.00]);
+        assert_eq!(mode.fractional_rate(), Some(59.94));
+        assert_eq!(hdmi.find_mode(2560, 1440).unwrap().refresh, vec![59.95]);
+        assert_eq!(hdmi.find_mode(2560, 1440).unwrap().fractional_rate(), None);
+        let (mut plan, _) = monitors.force_mode_plan((1920, 1080));
+        monitors.prefer_fractional_rates(&mut plan);
+        assert_eq!(plan.outputs[0].rate, None);
+        assert_eq!(plan.outputs[1].rate, Some(59.94));
+        assert!(plan
+            .command_string()
+            .windows(2)
+            .any(|pair| pair == ["--rate", "59.94"]));
+    }
+
+    #[test]
+    fn test_mode_rate_suffixes() {
+        let mode = Mode::parse("   640x480       60.00d   59.94").unwrap();
+        assert_eq!(mode.resolution, Resolution::new(640, 480));
+        assert_eq!(mode.refresh, vec![60.00, 59.94]);
+        assert!(mode.doublescan);
+        assert!(!mode.interlaced);
+        let mode = Mode::parse("   1920x1080i    60.00*+  50.00i").unwrap();
+        assert_eq!(mode.resolution, Resolution::new(1920, 1080));
+        assert_eq!(mode.refresh, vec![60.00, 50.00]);
+        assert!(mode.interlaced);
+        assert!(!mode.doublescan);
+    }
+
+    #[test]
+    fn test_mode_comma_rates() {
+        let mode = Mode::parse("   1920x1080     60.00,59.94,50.00").unwrap();
+        assert_eq!(mode.refresh, vec![60.00, 59.94, 50.00]);
+        let mode = Mode::parse("   1920x1080     60.00*+,59.94, 50.00").unwrap();
+        assert_eq!(mode.refresh, vec![60.00, 59.94, 50.00]);
+        assert_eq!(mode.current_rate, Some(60.00));
+        assert_eq!(mode.preferred_rate, Some(60.00));
+    }
+
+    #[test]
+    fn test_mode_approximate_rate() {
+        let mode = Mode::parse("   2560x1440     ~59.951*  ~143.912").unwrap();
+        assert_eq!(mode.refresh, vec![59.951, 143.912]);
+        assert_eq!(mode.current_rate, Some(59.951));
+    }
+
+    #[test]
+    fn test_primary_chain() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let chain = vec!["DP-2".to_string(), "eDP-1".to_string()];
+        assert_eq!(monitors.pick_preferred(&chain).unwrap().name, "eDP-1");
+        let chain = vec!["DP-2".to_string(), "DP-3".to_string()];
+        assert!(monitors.pick_preferred(&chain).is_none());
+        assert!(monitors.pick_preferred(&[]).is_none());
+    }
+
+    #[test]
+    fn test_empty_monitors() {
+        let monitors = Monitors {
+            monitors: Vec::new(),
+            screen: None,
+            warnings: Vec::new(),
+            output_names: Vec::new(),
+        };
+        assert!(monitors.largest().is_none());
+        assert!(monitors
+            .single_on_plan("HDMI-1-0")
+            .command_string()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_clone_primary_to_all() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let plan = monitors.clone_plan("HDMI-1-0").unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--same-as",
+                "HDMI-1-0",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "1920x1200",
+                "--primary"
+            ]
+        );
+        assert!(monitors.clone_plan("DP-1").is_err());
+    }
+
+    #[test]
+    fn test_mirror_already_active() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let plan = monitors.mirror_plan("HDMI-1-0");
+        let mirrored = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +*HDMI-1-0 1920/597x1200/336+0+0  HDMI-1-0
+ 1: +eDP-1 1920/301x1200/188+0+0  eDP-1",
+        )
+        .unwrap();
+        assert!(!plan.needs_change(&mirrored));
+        let extended = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +*HDMI-1-0 1920/597x1200/336+0+0  HDMI-1-0
+ 1: +eDP-1 1920/301x1200/188+1920+0  eDP-1",
+        )
+        .unwrap();
+        assert!(plan.needs_change(&extended));
+    }
+
+    #[test]
+    fn test_mirror_plan() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(
+            monitors.mirror_plan("HDMI-1-0").command_string(),
+            monitors.clone_plan("HDMI-1-0").unwrap().command_string()
+        );
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   1024x600      60.00 +\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert!(monitors.clone_plan("HDMI-1-0").is_err());
+        let plan = monitors.arranged_plan(Arrangement::Mirror, "HDMI-1-0");
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--auto",
+                "--same-as",
+                "HDMI-1-0",
+                "--output",
+                "DP-2",
+                "--auto",
+                "--same-as",
+                "HDMI-1-0",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--primary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fractional_position() {
+        let output = OUTPUT.replace("2560x1440+0+0", "2560x1440+0.5+0");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert!(monitors.warnings.is_empty());
+        assert_eq!(monitors.monitors[1].position, Some((0, 0)));
+        assert_eq!(parse_position("1920x1080+2560.75+0"), Some((2560, 0)));
+    }
+
+    #[test]
+    fn test_skip_unchanged_primary() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        plan.skip_unchanged_primary(&monitors);
+        assert!(plan.command_string().contains(&"--primary".to_string()));
+        let output = OUTPUT
+            .replace("eDP-1 connected primary", "eDP-1 connected")
+            .replace("HDMI-1-0 connected", "HDMI-1-0 connected primary");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan(&monitors.largest().unwrap().name);
+        plan.skip_unchanged_primary(&monitors);
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_primary() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        assert!(plan.command_string().contains(&"--primary".to_string()));
+        plan.clear_primary();
+        assert!(!plan.command_string().contains(&"--primary".to_string()));
+        let mut plan = monitors.clone_plan("HDMI-1-0").unwrap();
+        plan.clear_primary();
+        assert!(!plan.command_string().contains(&"--primary".to_string()));
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        plan.clear_primary();
+        assert!(!plan.needs_change(&current));
+    }
+
+    #[test]
+    fn test_pick_with() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let most_modes = monitors.pick_with(|m| m.modes.len()).unwrap();
+        assert_eq!(most_modes.name, "eDP-1");
+        let tied = monitors.pick_with(|_| 0).unwrap();
+        assert_eq!(tied.name, "eDP-1");
+        assert_eq!(monitors.largest().unwrap().name, "HDMI-1-0");
+    }
+
+    #[test]
+    fn test_screen_info() {
+        let spaced = ScreenInfo::parse(
+            "Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384",
+        )
+        .unwrap();
+        let unspaced =
+            ScreenInfo::parse("Screen 0: minimum 320x200, current 2560x1440, maximum 16384x16384")
+                .unwrap();
+        assert_eq!(spaced, unspaced);
+        assert_eq!(spaced.minimum, (320, 200));
+        assert_eq!(spaced.current, (2560, 1440));
+        assert_eq!(spaced.maximum, (16384, 16384));
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.screen, Some(spaced));
+        assert!(monitors.warnings.is_empty());
+        let plan = monitors.single_on_plan("HDMI-1-0");
+        assert!(plan.exceeds_screen(&spaced).is_none());
+        let small = ScreenInfo {
+            maximum: (2048, 2048),
+            ..spaced
+        };
+        assert!(plan.exceeds_screen(&small).is_some());
+    }
+
+    #[test]
+    fn test_orientation_rotates_internal() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.keep_only_plan(&monitors.names()).unwrap();
+        let mut source = FakeOrientation("left".parse().unwrap());
+        plan.rotate_internal(source.orientation().unwrap(), &default_internal_prefixes());
+        assert_eq!(plan.outputs[0].rotate, Some(Rotation::Left));
+        assert_eq!(plan.outputs[1].rotate, None);
+        assert_eq!(
+            plan.outputs[0].command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--rotate",
+                "left"
+            ]
+        );
+        assert!(plan.changes_rotation(&monitors));
+        let rotated_output = OUTPUT.replace(
+            "eDP-1 connected primary (normal",
+            "eDP-1 connected primary 1200x1920+0+0 left (normal",
+        );
+        let rotated = Monitors::from_query(rotated_output.trim_end()).unwrap();
+        assert_eq!(rotated.monitors[0].rotation, Rotation::Left);
+        assert!(!plan.changes_rotation(&rotated));
+        assert!(plan.outputs[0].shows_as(1200, 1920));
+        assert!(!plan.outputs[0].shows_as(1920, 1200));
+    }
+
+    #[test]
+    fn test_ppi_report() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.monitors[1].phys_mm, Some((597, 336)));
+        assert_eq!(monitors.monitors[0].phys_mm, None);
+        let dpi = monitors.monitors[1].dpi().unwrap();
+        assert!((108.0..110.0).contains(&dpi));
+        assert_eq!(
+            monitors.ppi_report(),
+            vec!["eDP-1 unknown", "HDMI-1-0 108.9"]
+        );
+    }
+
+    #[test]
+    fn test_mode_override_beats_profile() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let config = Config::from_toml(
+            r#"
+[profiles.desk]
+eDP-1 = { mode = "1280x800" }
+HDMI-1-0 = { mode = "1920x1080", primary = true }
+"#,
+        )
+        .unwrap();
+        let mut plan = monitors
+            .layout_plan(config.profile("desk").unwrap())
+            .unwrap();
+        plan.override_modes(&[("HDMI-1-0".to_string(), (2560, 1440))])
+            .unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1280x800",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--primary"
+            ]
+        );
+        assert!(config.profile("missing").is_err());
+        assert!(plan
+            .override_modes(&[("DP-1".to_string(), (1920, 1080))])
+            .is_err());
+    }
+
+    #[test]
+    fn test_symbolic_mode_override() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let requests = [
+            ("HDMI-1-0".to_string(), "preferred".parse().unwrap()),
+            ("eDP-1".to_string(), "max".parse().unwrap()),
+        ];
+        assert_eq!(
+            monitors.resolve_modes(&requests).unwrap(),
+            vec![
+                ("HDMI-1-0".to_string(), (2560, 1440)),
+                ("eDP-1".to_string(), (1920, 1200))
+            ]
+        );
+        assert_eq!(
+            "1280x720".parse::<ModeRequest>().unwrap(),
+            ModeRequest::Size(1280, 720)
+        );
+        assert!("biggest".parse::<ModeRequest>().is_err());
+    }
+
+    #[test]
+    fn test_delete_virtual_command() {
+        let listmonitors = "Monitors: 3
+ 0: +*DP-1~1 1920/400x1080/340+0+0  DP-1
+ 1: +DP-1~2 1920/400x1080/340+1920+0
+ 2: +HDMI-1-0 2560/597x1440/336+3840+0  HDMI-1-0";
+        assert_eq!(
+            delete_virtual_command(listmonitors).unwrap(),
+            ["--delmonitor", "DP-1~1", "--delmonitor", "DP-1~2"]
+        );
+        assert!(delete_virtual_command(
+            "Monitors: 1\n 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0"
+        )
+        .unwrap()
+        .is_empty());
+        assert!(delete_virtual_command("Monitors: 1\n 0:").is_err());
+    }
+
+    const WLR_RANDR: &str = r#"
+eDP-1 "Sharp Corporation 0x1453 (eDP-1)"
+  Make: Sharp Corporation
+  Model: 0x1453
+  Serial: (null)
+  Physical size: 290x190 mm
+  Enabled: yes
+  Modes:
+    1920x1200 px, 59.950001 Hz (preferred, current)
+    1920x1080 px, 60.000000 Hz
+  Position: 0,0
+  Transform: 90
+  Scale: 1.000000
+HDMI-A-1 "Dell Inc. DELL U2719D (HDMI-A-1)"
+  Physical size: 600x340 mm
+  Enabled: no
+  Modes:
+    2560x1440 px, 59.951000 Hz
+    2560x1440 px, 74.968002 Hz
+    1920x1080 px, 60.000000 Hz
+  Position: 1920,0
+  Transform: normal
+"#;
+
+    #[test]
+    fn test_from_wlr_text() {
+        let monitors = Monitors::from_wlr_text(WLR_RANDR).unwrap();
+        assert_eq!(monitors.names(), vec!["eDP-1", "HDMI-A-1"]);
+        let panel = &monitors.monitors[0];
+        assert_eq!(panel.resolution, Resolution::new(1920, 1200));
+        assert_eq!(panel.refresh, Some(59.95));
+        assert_eq!(panel.modes[0].current_rate, Some(59.95));
+        assert_eq!(panel.position, Some((0, 0)));
+        assert_eq!(panel.phys_mm, Some((290, 190)));
+        assert_eq!(panel.rotation, Rotation::Left);
+        let hdmi = &monitors.monitors[1];
+        assert_eq!(hdmi.resolution, Resolution::new(2560, 1440));
+        assert_eq!(hdmi.highest_rate(2560, 1440), Some(74.968));
+        assert_eq!(hdmi.position, None);
+        assert_eq!(monitors.largest().unwrap().name, "HDMI-A-1");
+        assert!(Monitors::from_wlr_text("DP-1 \"No modes\"\n  Enabled: no").is_err());
+        let mut plan = monitors.arranged_plan(Arrangement::Extend, "HDMI-A-1");
+        monitors.set_highest_rates(&mut plan);
+        assert_eq!(
+            plan.wlr_command_string().unwrap(),
+            [
+                "--output",
+                "eDP-1",
+                "--on",
+                "--mode",
+                "1920x1200@59.950Hz",
+                "--pos",
+                "0,0",
+                "--output",
+                "HDMI-A-1",
+                "--on",
+                "--mode",
+                "2560x1440@74.968Hz",
+                "--pos",
+                "1920,0",
+            ]
+        );
+        let plan = monitors.arranged_plan(Arrangement::Mirror, "HDMI-A-1");
+        assert!(plan.wlr_command_string().is_err());
+        let plan = monitors.single_on_plan("HDMI-A-1");
+        assert_eq!(
+            plan.wlr_command_string().unwrap()[..3],
+            ["--output", "eDP-1", "--off"]
+        );
+    }
+
+    #[test]
+    fn test_area_saturates() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            &format!(
+                "DP-2 connected\n   {}x{}     60.00\nDP-3-0 disconnected",
+                usize::MAX / 2,
+                4
+            ),
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let huge = monitors.find("DP-2").unwrap();
+        assert_eq!(huge.area(), usize::MAX);
+        assert_eq!(huge.modes[0].area(), usize::MAX);
+        assert_eq!(monitors.largest().unwrap().name, "DP-2");
+        assert_eq!(monitors.warnings.len(), 1);
+        assert!(monitors.warnings[0].contains("too many pixels"));
+        assert!(monitors.check_strict().is_err());
+        let plan = monitors.single_on_plan("DP-2");
+        assert!(monitors.bandwidth_warnings(&plan).is_empty());
+    }
+
+    #[test]
+    fn test_sticky_primary() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   2560x1440    144.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let picked = monitors.largest().unwrap();
+        assert_eq!(picked.name, "DP-2");
+        assert_eq!(monitors.sticky(picked, "HDMI-1-0").name, "HDMI-1-0");
+        assert_eq!(monitors.sticky(picked, "eDP-1").name, "DP-2");
+        assert_eq!(monitors.sticky(picked, "DP-4").name, "DP-2");
+    }
+
+    #[test]
+    fn test_extend_plan() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let plan = monitors.arranged_plan("extend".parse().unwrap(), "HDMI-1-0");
+        assert_eq!(
+            plan.command_string(),
+            [
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--pos",
+                "0x0",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--pos",
+                "1920x0",
+                "--primary",
+            ]
+        );
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +eDP-1 1920/301x1200/188+0+0  eDP-1
+ 1: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        assert!(plan.needs_change(&current));
+        let mut rotated = monitors.extend_plan("HDMI-1-0");
+        rotated
+            .set_rotations(&[("eDP-1".to_string(), Rotation::Left)])
+            .unwrap();
+        rotated.place_side_by_side();
+        assert_eq!(rotated.outputs[0].footprint(), Some((1200, 1920)));
+        assert_eq!(
+            rotated.command_string()[..8],
+            [
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--pos",
+                "0x0",
+                "--rotate",
+                "left"
+            ]
+        );
+        assert_eq!(rotated.outputs[1].position, Some((1200, 0)));
+        let mut scaled = monitors.extend_plan("eDP-1");
+        scaled.scale_primary(1.5).unwrap();
+        scaled.place_side_by_side();
+        assert_eq!(
+            scaled.command_string()[..8],
+            [
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--pos",
+                "0x0",
+                "--scale",
+                "1.5x1.5"
+            ]
+        );
+        assert_eq!(scaled.outputs[1].position, Some((2880, 0)));
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 2
+ 0: +*eDP-1 2880/301x1800/188+0+0  eDP-1
+ 1: +HDMI-1-0 2560/597x1440/336+2880+0  HDMI-1-0",
+        )
+        .unwrap();
+        assert!(!scaled.needs_change(&current));
+        assert!(rotated
+            .set_rotations(&[("DP-1".to_string(), Rotation::Right)])
+            .is_err());
+        let single = monitors.arranged_plan(Arrangement::default(), "HDMI-1-0");
+        assert_eq!(
+            single.command_string(),
+            monitors.single_on_plan("HDMI-1-0").command_string()
+        );
+        assert!("stack".parse::<Arrangement>().is_err());
+    }
+
+    #[test]
+    fn test_split_command() {
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*DP-1 3840/800x1080/340+0+0  DP-1",
+        )
+        .unwrap();
+        let monitor = current.find("DP-1").unwrap();
+        assert_eq!(
+            monitor.split_command(2).unwrap(),
+            [
+                "--setmonitor",
+                "DP-1~1",
+                "1920/400x1080/340+0+0",
+                "DP-1",
+                "--setmonitor",
+                "DP-1~2",
+                "1920/400x1080/340+1920+0",
+                "none",
+            ]
+        );
+        assert!(monitor.split_command(7).is_err());
+        assert!(monitor.split_command(0).is_err());
+    }
+
+    #[test]
+    fn test_height_modes() {
+        let chunk = [
+            "DP-1 connected (normal left inverted right x axis y axis)",
+            "   2560x1440     59.95 +",
+            "   1280x1080     60.00",
+            "   1920x1080     60.00    50.00",
+            "   1440x1080     60.00",
+            "   1280x720      60.00",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        let monitors = Monitors {
+            monitors: vec![monitor],
+            screen: None,
+            warnings: Vec::new(),
+            output_names: vec!["DP-1".to_string()],
+        };
+        let mut plan = monitors.single_on_plan("DP-1");
+        assert!(monitors.height_modes(&mut plan, 1080).is_empty());
+        assert_eq!(plan.outputs[0].mode, Some((1920, 1080)));
+        let warnings = monitors.height_modes(&mut plan, 2160);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(plan.outputs[0].mode, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_fastest_modes() {
+        let chunk = [
+            "DP-1 connected (normal left inverted right x axis y axis)",
+            "   2560x1440     59.95 +",
+            "   1920x1080    144.00    60.00",
+            "   1280x720     144.00",
+            "   640x480      240.00d",
+        ];
+        let monitor = Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap();
+        let monitors = Monitors {
+            monitors: vec![monitor],
+            screen: None,
+            warnings: Vec::new(),
+            output_names: vec!["DP-1".to_string()],
+        };
+        let mut plan = monitors.single_on_plan("DP-1");
+        assert_eq!(plan.outputs[0].mode, Some((2560, 1440)));
+        monitors.fastest_modes(&mut plan);
+        monitors.set_highest_rates(&mut plan);
+        assert_eq!(plan.outputs[0].mode, Some((1920, 1080)));
+        assert_eq!(plan.outputs[0].rate, Some(144.0));
+    }
+
+    #[test]
+    fn test_label_command() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let largest = monitors.largest().unwrap();
+        assert_eq!(
+            largest.label_command("main"),
+            ["--setmonitor", "main", "auto", "HDMI-1-0"]
+        );
+    }
+
+    #[test]
+    fn test_largest_by_area_then_rate() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   2560x1080     60.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.largest().unwrap().name, "HDMI-1-0");
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   2560x1440    144.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.largest().unwrap().name, "DP-2");
+        // Same area and rate keeps the first listed.
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   2560x1440     59.95\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.largest().unwrap().name, "DP-2");
+    }
+
+    #[test]
+    fn test_smallest() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.smallest().unwrap().name, "eDP-1");
+        let strategy = "smallest".parse().unwrap();
+        assert_eq!(monitors.select(strategy).unwrap().name, "eDP-1");
+        assert_eq!(
+            monitors.select(Strategy::default()).unwrap().name,
+            "HDMI-1-0"
+        );
+        // Equal areas keep the first listed, as with the largest.
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   1200x1920     60.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.smallest().unwrap().name, "eDP-1");
+        assert!("tiniest".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_physical_size() {
+        let strategy = "physical-size".parse().unwrap();
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.select(strategy).unwrap().name, "HDMI-1-0");
+        // A TV bigger than the monitor but at a lower resolution.
+        let output = OUTPUT.replace(
+            "eDP-1 connected primary (normal left inverted right x axis y axis)",
+            "eDP-1 connected primary (normal left inverted right x axis y axis) 1210mm x 680mm",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.select(strategy).unwrap().name, "eDP-1");
+        assert_eq!(monitors.largest().unwrap().name, "HDMI-1-0");
+        // Without any sizes the pixel area decides.
+        let output = OUTPUT.replace(" 597mm x 336mm", "");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.select(strategy).unwrap().name, "HDMI-1-0");
+    }
+
+    #[test]
+    fn test_merge_verbose() {
+        let verbose = "Screen 0: minimum 320 x 200, current 2560 x 1440, maximum 16384 x 16384
+eDP-1 connected primary (normal left inverted right x axis y axis)
+	Identifier: 0x42
+  1920x1200 (0x43) 154.000MHz +HSync -VSync +preferred
+        h: width  1920 start 1968 end 2000 total 2080 skew    0 clock  74.04KHz
+        v: height 1200 start 1203 end 1209 total 1235           clock  60.10Hz
+  1920x1200 (0x44) 193.250MHz -HSync +VSync
+        h: width  1920 start 2056 end 2256 total 2592 skew    0 clock  74.56KHz
+        v: height 1200 start 1203 end 1209 total 1245           clock  59.89Hz
+  3840x2160 (0x45) 533.250MHz +HSync -VSync
+        h: width  3840 start 3888 end 3920 total 4000 skew    0 clock 133.31KHz
+        v: height 2160 start 2163 end 2168 total 2222           clock  60.00Hz
+HDMI-1-0 connected 2560x1440+0+0 (0x46) normal (normal left inverted right x axis y axis) 597mm x 336mm
+  2560x1440 (0x47) 241.500MHz +HSync -VSync *current +preferred
+        h: width  2560 start 2608 end 2640 total 2720 skew    0 clock  88.79KHz
+        v: height 1440 start 1443 end 1448 total 1481           clock  59.95Hz
+";
+        let mut monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let before = monitors.find("eDP-1").unwrap().modes.len();
+        monitors.merge_verbose(verbose);
+        monitors.merge_verbose(verbose);
+        let edp = monitors.find("eDP-1").unwrap();
+        assert_eq!(edp.modes.len(), before);
+        let mode = edp.find_mode(1920, 1200).unwrap();
+        assert_eq!(mode.pixel_clocks, vec![(60.10, 154.0)]);
+        assert_eq!(mode.pixel_clock(60.10), Some(154.0));
+        assert_eq!(mode.pixel_clock(40.06), None);
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        assert_eq!(hdmi.modes[0].pixel_clocks, vec![(59.95, 241.5)]);
+    }
+
+    #[test]
+    fn test_auto_rotate_from_edid() {
+        let verbose = "Screen 0: minimum 320 x 200, current 3120 x 1920, maximum 16384 x 16384
+DSI-1 connected primary 1200x1920+0+0 (0x4a) normal (normal left inverted right x axis y axis) 135mm x 216mm
+\tIdentifier: 0x49
+\tEDID:
+\t\t00ffffffffffff000000000000000000
+\t\t00000000000000000000000000000000
+\t\t00000000000000000000000000000000
+\t\t0000000000001d5ab0a0408050700000
+\t\t00005ed2000000000000000000000000
+\t\t00000000000000000000000000000000
+\t\t00000000000000000000000000000000
+\t\t0000000000000000000000000000008f
+\tBorderDimensions: 4
+  1200x1920 (0x4b) 156.000MHz +HSync -VSync *current +preferred
+HDMI-1 connected 1920x1080+1200+0 (0x4c) normal (normal left inverted right x axis y axis) 527mm x 296mm
+\tEDID:
+\t\t00ffffffffffff000000000000000000
+\t\t00000000000000000000000000000000
+\t\t00000000000000000000000000000000
+\t\t000000000000023a801871382d40582c
+\t\t4500132a2100001e0000000000000000
+\t\t00000000000000000000000000000000
+\t\t00000000000000000000000000000000
+\t\t00000000000000000000000000000000
+";
+        let edids = edid::parse_verbose(verbose);
+        assert_eq!(edids["DSI-1"].len(), 128);
+        assert_eq!(edid::rotation_hint(&edids["DSI-1"]), Some(Rotation::Left));
+        assert_eq!(edid::rotation_hint(&edids["HDMI-1"]), None);
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DSI-1 connected\n   1200x1920     60.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let mut plan = monitors.keep_only_plan(&["DSI-1".to_string()]).unwrap();
+        plan.rotate_from_edid(&edids);
+        let dsi = plan.outputs.iter().find(|o| o.name == "DSI-1").unwrap();
+        assert_eq!(
+            dsi.command_string(),
+            vec![
+                "--output",
+                "DSI-1",
+                "--mode",
+                "1200x1920",
+                "--rotate",
+                "left",
+                "--primary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bandwidth_warning() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        assert!(monitors.bandwidth_warnings(&plan).is_empty());
+        plan.outputs[1].mode = Some((3840, 2160));
+        plan.outputs[1].rate = Some(60.0);
+        let warnings = monitors.bandwidth_warnings(&plan);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("HDMI-1-0 needs roughly 597MHz"));
+        // eDP isn't a cable at all.
+        let mut plan = monitors.single_on_plan("eDP-1");
+        plan.outputs[0].mode = Some((3840, 2160));
+        plan.outputs[0].rate = Some(60.0);
+        assert!(monitors.bandwidth_warnings(&plan).is_empty());
+    }
+
+    #[test]
+    fn test_single_primary_flag() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        for name in ["eDP-1", "HDMI-1-0"] {
+            let args = monitors.single_on_plan(name).command_string();
+            let primaries = args
+                .iter()
+                .enumerate()
+                .filter(|(_, arg)| *arg == "--primary")
+                .map(|(i, _)| i)
+                .collect::<Vec<usize>>();
+            assert_eq!(primaries.len(), 1);
+            let output = args[..primaries[0]]
+                .iter()
+                .rposition(|arg| arg == "--output")
+                .unwrap();
+            assert_eq!(args[output + 1], name);
+        }
+    }
+
+    #[test]
+    fn test_select_by_name_regex() {
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DP-2 connected\n   1920x1080     60.00\nDP-3 connected\n   3440x1440     60.00\nDP-3-0 disconnected",
+        ).replace("DP-3 disconnected", "DP-4 disconnected");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.largest().unwrap().name, "DP-3");
+        let dp = monitors.matching(&Regex::new("^DP").unwrap()).unwrap();
+        assert_eq!(dp.names(), vec!["DP-2", "DP-3"]);
+        assert_eq!(dp.largest().unwrap().name, "DP-3");
+        let hdmi = monitors.matching(&Regex::new("^HDMI").unwrap()).unwrap();
+        assert_eq!(hdmi.largest().unwrap().name, "HDMI-1-0");
+        assert!(monitors.matching(&Regex::new("^VGA").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_list_active_only() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let selected = monitors.largest().map(|m| m.name.as_str());
+        assert_eq!(
+            monitors.list(false, selected),
+            vec![
+                "eDP-1 1920x1200@60.10 primary",
+                "HDMI-1-0 2560x1440@59.95 selected"
+            ]
+        );
+        assert_eq!(monitors.list(false, None)[1], "HDMI-1-0 2560x1440@59.95");
+        assert_eq!(monitors.list(true, None), vec!["HDMI-1-0 2560x1440@59.95"]);
+        let modes = monitors.list_modes(false, true);
+        assert_eq!(modes[0], "HDMI-1-0");
+        assert_eq!(modes.len(), 16);
+    }
+
+    #[test]
+    fn test_dedupe_modes() {
+        let output = OUTPUT.replace(
+            "   640x480       75.00    59.94    59.93",
+            "   640x480       75.00    59.94    59.93\n   640x480       60.00    75.00",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let listing = monitors.list_modes(false, false);
+        assert_eq!(listing[1], "   1920x1200  60.10 60.10 40.06");
+        assert!(listing.contains(&"   640x480  60.00 75.00".to_string()));
+        let listing = monitors.list_modes(true, false);
+        assert_eq!(listing[0], "eDP-1");
+        assert_eq!(listing[1], "   1920x1200  60.10 40.06");
+        assert_eq!(listing.last().unwrap(), "   640x480  75.00 59.94 60.00");
+        assert_eq!(
+            listing
+                .iter()
+                .filter(|line| line.contains("640x480"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_print_command() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let plan = monitors.single_on_plan(&monitors.largest().unwrap().name);
+        assert_eq!(
+            plan.shell_command(),
+            "xrandr --output eDP-1 --off --output HDMI-1-0 --mode 2560x1440 --primary"
+        );
+        assert_eq!(shell_quote("DP 1"), "'DP 1'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_internal_prefixes() {
+        assert!(is_internal("eDP-1", &default_internal_prefixes()));
+        assert!(!is_internal("DSI-1", &default_internal_prefixes()));
+        let config = Config::from_toml(r#"internal_prefixes = ["DSI", "eDP"]"#).unwrap();
+        let prefixes = config.internal_prefixes.unwrap();
+        assert!(is_internal("DSI-1", &prefixes));
+        assert!(!is_internal("LVDS-1", &prefixes));
+        let output = OUTPUT.replace(
+            "DP-2 disconnected",
+            "DSI-1 connected\n   1200x1920     60.00\nDP-3-0 disconnected",
+        );
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        let mut plan = monitors.keep_only_plan(&monitors.names()).unwrap();
+        plan.rotate_internal(Rotation::Right, &prefixes);
+        let rotated = plan
+            .outputs
+            .iter()
+            .filter(|o| o.rotate.is_some())
+            .map(|o| o.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(rotated, vec!["eDP-1", "DSI-1"]);
+    }
+
+    #[test]
+    fn test_panning() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("eDP-1");
+        let panning = vec![("eDP-1".to_string(), "3840x2400+0+0".parse().unwrap())];
+        plan.set_panning(&panning).unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--mode",
+                "1920x1200",
+                "--panning",
+                "3840x2400+0+0",
+                "--primary",
+                "--output",
+                "HDMI-1-0",
+                "--off"
+            ]
+        );
+        let panning = vec![("HDMI-1-0".to_string(), "3840x2400+0+0".parse().unwrap())];
+        assert!(plan.set_panning(&panning).is_err());
+        assert!("3840x2400".parse::<Panning>().is_err());
+        assert!("3840x2400+0".parse::<Panning>().is_err());
+        assert!("3840x2400+a+0".parse::<Panning>().is_err());
+    }
+
+    #[test]
+    fn test_set_properties() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        let properties = vec![(
+            "HDMI-1-0".to_string(),
+            ("Broadcast RGB".to_string(), "Full".to_string()),
+        )];
+        plan.set_properties(&properties).unwrap();
+        assert_eq!(
+            plan.command_string(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--mode",
+                "2560x1440",
+                "--set",
+                "Broadcast RGB",
+                "Full",
+                "--primary"
+            ]
+        );
+        let current = Monitors::from_listactivemonitors(
+            "Monitors: 1
+ 0: +*HDMI-1-0 2560/597x1440/336+0+0  HDMI-1-0",
+        )
+        .unwrap();
+        assert!(plan.needs_change(&current));
+        let properties = vec![(
+            "eDP-1".to_string(),
+            ("underscan".to_string(), "on".to_string()),
+        )];
+        assert!(plan.set_properties(&properties).is_err());
+    }
+
+    #[test]
+    fn test_lid_closed_skips_internal() {
+        let output = OUTPUT.replace("   1920x1200     60.10 +", "   3840x2400     60.10 +");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(monitors.largest().unwrap().name, "eDP-1");
+        let prefixes = default_internal_prefixes();
+        assert_eq!(
+            monitors
+                .select_with_lid(Strategy::Largest, false, &prefixes)
+                .unwrap()
+                .name,
+            "eDP-1"
+        );
+        assert_eq!(
+            monitors
+                .select_with_lid(Strategy::Largest, true, &prefixes)
+                .unwrap()
+                .name,
+            "HDMI-1-0"
+        );
+        let laptop_only = monitors.filtered(|m| is_internal(&m.name, &prefixes));
+        assert_eq!(
+            laptop_only
+                .select_with_lid(Strategy::Largest, true, &prefixes)
+                .unwrap()
+                .name,
+            "eDP-1"
+        );
+    }
+
+    #[test]
+    fn test_best_mode_within() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let hdmi = monitors.find("HDMI-1-0").unwrap();
+        let best = |w, h| {
+            hdmi.best_mode_within(w, h)
+                .map(|mode| (mode.resolution.width, mode.resolution.height))
+        };
+        assert_eq!(best(2560, 1440), Some((2560, 1440)));
+        assert_eq!(best(2000, 1200), Some((1920, 1200)));
+        assert_eq!(best(1920, 1080), Some((1920, 1080)));
+        assert_eq!(best(100, 100), None);
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        assert!(monitors.cap_modes(&mut plan, (1920, 1080)).is_empty());
+        assert_eq!(plan.outputs[1].mode, Some((1920, 1080)));
+        assert_eq!(monitors.cap_modes(&mut plan, (100, 100)).len(), 1);
+    }
+
+    #[test]
+    fn test_apply_order() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("HDMI-1-0");
+        plan.order_outputs("on-first".parse().unwrap());
+        assert_eq!(
+            plan.command_string()[..4],
+            ["--output", "HDMI-1-0", "--mode", "2560x1440"]
+        );
+        assert_eq!(plan.command_string()[5..], ["--output", "eDP-1", "--off"]);
+        plan.order_outputs("off-first".parse().unwrap());
+        assert_eq!(plan.command_string()[..3], ["--output", "eDP-1", "--off"]);
+        assert_eq!(plan.command_string()[3..5], ["--output", "HDMI-1-0"]);
+        assert!("sideways".parse::<ApplyOrder>().is_err());
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let output = OUTPUT
+            .replace(
+                "eDP-1 connected primary (normal",
+                "eDP-1 connected primary 1920x1200+2560+0 (normal",
+            )
+            .replace("1920x1200     60.10 +", "1920x1200     60.10*+");
+        let monitors = Monitors::from_query(output.trim_end()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&monitors.summary()).unwrap(),
+            r#"[{"name":"eDP-1","resolution":"1920x1200","rate":60.1,"primary":true},{"name":"HDMI-1-0","resolution":"2560x1440","rate":59.95,"primary":false}]"#
+        );
+    }
+
+    #[test]
+    fn test_set_rates() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let mut plan = monitors.single_on_plan("eDP-1");
+        let modes = [("eDP-1".to_string(), ModeRequest::Size(1920, 1200))];
+        plan.override_modes(&monitors.resolve_modes(&modes).unwrap())
+            .unwrap();
+        monitors
+            .set_rates(&mut plan, &[("eDP-1".to_string(), 40.06)])
+            .unwrap();
+        assert_eq!(plan.outputs[0].rate, Some(40.06));
+        // 40.06Hz is only offered at 1920x1200.
+        let modes = [("eDP-1".to_string(), ModeRequest::Size(1920, 1080))];
+        plan.override_modes(&monitors.resolve_modes(&modes).unwrap())
+            .unwrap();
+        let error = monitors
+            .set_rates(&mut plan, &[("eDP-1".to_string(), 40.06)])
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "eDP-1 doesn't offer 40.06Hz at 1920x1080"
+        );
+        assert!(monitors
+            .set_rates(&mut plan, &[("HDMI-1-0".to_string(), 60.0)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_debug_model() {
+        let dump = Monitors::debug_model(OUTPUT);
+        let (raw, parsed) = dump.split_once("--- parsed ---").unwrap();
+        assert!(raw.contains(OUTPUT.trim_end()));
+        assert!(parsed.contains(r#"name: "eDP-1""#));
+        assert!(parsed.contains(r#"name: "HDMI-1-0""#));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        let selected = monitors.select(Strategy::Largest).map(|m| m.name.as_str());
+        let report = serde_json::to_value(monitors.report(selected)).unwrap();
+        assert_eq!(report["selected"], "HDMI-1-0");
+        assert_eq!(report["monitors"].as_array().unwrap().len(), 2);
+        let hdmi = &report["monitors"][1];
+        assert_eq!(hdmi["name"], "HDMI-1-0");
+        assert_eq!(
+            (hdmi["width"].as_u64(), hdmi["height"].as_u64()),
+            (Some(2560), Some(1440))
+        );
+        assert_eq!(hdmi["refresh"].as_f64(), Some(59.95_f32 as f64));
+        assert_eq!(hdmi["primary"], false);
+        assert_eq!(hdmi["state"], "connected");
+        assert_eq!(report["monitors"][0]["primary"], true);
+        let nothing = Monitors::from_query(
+            "Screen 0: minimum 320 x 200, current 640 x 480, maximum 16384 x 16384
+eDP-1 disconnected (normal left inverted right x axis y axis)",
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_string(&nothing.report(None)).unwrap(),
+            r#"{"monitors":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_provider_filter() {
+        let monitors = Monitors::from_query(OUTPUT.trim_end()).unwrap();
+        assert_eq!(monitors.output_names.len(), 13);
+        let providers = Provider::parse_list(
+            "Providers: number : 2
+Provider 0: id: 0x47 cap: 0xf, Source Output, Sink Output crtcs: 3 outputs: 8 associated providers: 1 name:modesetting
+Provider 1: id: 0x1f8 cap: 0x2, Sink Output crtcs: 4 outputs: 5 associated providers: 1 name:NVIDIA-G0",
+        )
+        .unwrap();
+        let nvidia = monitors.on_provider(&providers, "NVIDIA-G0").unwrap();
+        assert_eq!(nvidia.names(), vec!["HDMI-1-0"]);
+        let intel = monitors.on_provider(&providers, "modesetting").unwrap();
+        assert_eq!(intel.names(), vec!["eDP-1"]);
+    }
+
+    #[test]
+    fn test_streaming_matches_buffered() {
+        let buffered = buffered_chunks(OUTPUT)
+            .into_iter()
+            .skip(1)
+            .filter(|chunk| !chunk[0].contains("disconnected"))
+            .map(|chunk| Monitor::parse_monitor_from_listactivemonitors_chunk(&chunk).unwrap())
+            .collect::<Vec<Monitor>>();
+        let streamed = Monitors::from_query(OUTPUT).unwrap().monitors;
+        assert_eq!(format!("{:?}", streamed), format!("{:?}", buffered));
+        let largest_chunk = MonitorChunks::new(OUTPUT.trim().lines())
+            .map(|chunk| chunk.len())
+            .max();
+        assert_eq!(largest_chunk, Some(40));
+    }
+
+    struct FakeOrientation(Rotation);
+
+    impl OrientationSource for FakeOrientation {
+        fn orientation(&mut self) -> Result<Rotation> {
+            Ok(self.0)
+        }
+    }
+
+    fn default_internal_prefixes() -> Vec<String> {
+        DEFAULT_INTERNAL_PREFIXES.map(String::from).to_vec()
+    }
+
+    /// The original parser, collecting every line up front before chunking.
+    fn buffered_chunks(xrandr_outputs: &str) -> Vec<Vec<String>> {
+        let mut chunks = Vec::new();
+        let mut lines: Vec<String> = xrandr_outputs.trim().lines().map(String::from).collect();
+        let mut peak;
+        while lines.len() > 1 {
+            peak = 1;
+            let mut peak_line = &lines[peak];
+            while !peak_line.contains("connected") {
+                match lines.get(peak) {
+                    Some(p) => {
+                        peak_line = p;
+                    }
+                    None => break,
+                };
+                peak += 1
+            }
+            chunks.push(lines.drain(..peak).collect());
+        }
+        chunks
+    }
+}
@@ -0,0 +1,45 @@
+use crate::Rotation;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Somewhere to read the physical orientation of the machine from.
+pub trait OrientationSource {
+    fn orientation(&mut self) -> Result<Rotation>;
+}
+
+/// Reads an xrandr rotation name from a file, such as a sysfs attribute, or else from the
+/// stdout of a shell command.
+pub struct ExternalOrientation {
+    source: String,
+}
+
+impl ExternalOrientation {
+    pub fn new(source: &str) -> ExternalOrientation {
+        ExternalOrientation {
+            source: source.to_string(),
+        }
+    }
+}
+
+impl OrientationSource for ExternalOrientation {
+    fn orientation(&mut self) -> Result<Rotation> {
+        let path = Path::new(&self.source);
+        let orientation = if path.is_file() {
+            std::fs::read_to_string(path)
+                .context(format!("Failed to read orientation from {}", self.source))?
+        } else {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&self.source)
+                .output()
+                .context(format!("Failed to run orientation command {}", self.source))?;
+            if !output.status.success() {
+                bail!("Orientation command {} failed", self.source);
+            }
+            String::from_utf8(output.stdout)
+                .context("Non utf8 characters encountered in the orientation")?
+        };
+        orientation.trim().parse()
+    }
+}
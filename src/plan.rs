@@ -0,0 +1,798 @@
+use crate::edid;
+use crate::monitors::{is_internal, Monitors, ScreenInfo};
+use crate::resolution::Resolution;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A `--panning` area as `WxH+X+Y`, the framebuffer region the output scrolls around.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Panning {
+    pub width: usize,
+    pub height: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl FromStr for Panning {
+    type Err = anyhow::Error;
+
+    fn from_str(panning: &str) -> Result<Panning> {
+        let (size, offset) = panning
+            .split_once('+')
+            .context(format!("Expect panning to be WxH+X+Y: {}", panning))?;
+        let (width, height) = parse_mode(size)?;
+        let (x, y) = offset
+            .split_once('+')
+            .context(format!("Expect panning to be WxH+X+Y: {}", panning))?;
+        Ok(Panning {
+            width,
+            height,
+            x: x.parse()
+                .context(format!("Panning x should be an integer: {}", panning))?,
+            y: y.parse()
+                .context(format!("Panning y should be an integer: {}", panning))?,
+        })
+    }
+}
+
+impl TryFrom<String> for Panning {
+    type Error = anyhow::Error;
+
+    fn try_from(panning: String) -> Result<Panning> {
+        panning.parse()
+    }
+}
+
+impl fmt::Display for Panning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    #[default]
+    Normal,
+    Left,
+    Right,
+    Inverted,
+}
+
+impl Rotation {
+    /// Left and right rotations swap the width and height an output takes up.
+    pub fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Left | Rotation::Right)
+    }
+}
+
+/// Whether outputs being enabled or disabled come first in the xrandr command. Enabling first
+/// avoids a moment with no active CRTC, some drivers need a CRTC freed up first instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ApplyOrder {
+    #[default]
+    OnFirst,
+    OffFirst,
+}
+
+impl FromStr for ApplyOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(order: &str) -> Result<ApplyOrder> {
+        match order {
+            "on-first" => Ok(ApplyOrder::OnFirst),
+            "off-first" => Ok(ApplyOrder::OffFirst),
+            _ => bail!(
+                "Expected apply order to be on-first or off-first: {}",
+                order
+            ),
+        }
+    }
+}
+
+/// Mode asked for with `--mode NAME=...`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeRequest {
+    Size(usize, usize),
+    /// The mode xrandr marks with `+`.
+    Preferred,
+    /// The largest mode, the same one picked automatically.
+    Max,
+}
+
+impl FromStr for ModeRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<ModeRequest> {
+        match mode {
+            "preferred" => Ok(ModeRequest::Preferred),
+            "max" => Ok(ModeRequest::Max),
+            _ => parse_mode(mode).map(|(width, height)| ModeRequest::Size(width, height)),
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = anyhow::Error;
+
+    fn from_str(rotation: &str) -> Result<Rotation> {
+        match rotation {
+            "normal" => Ok(Rotation::Normal),
+            "left" => Ok(Rotation::Left),
+            "right" => Ok(Rotation::Right),
+            "inverted" => Ok(Rotation::Inverted),
+            _ => bail!(
+                "Expected rotation to be normal, left, right or inverted: {}",
+                rotation
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rotation = match self {
+            Rotation::Normal => "normal",
+            Rotation::Left => "left",
+            Rotation::Right => "right",
+            Rotation::Inverted => "inverted",
+        };
+        f.write_str(rotation)
+    }
+}
+
+/// Single quotes `arg` unless every character is safe in a shell word.
+pub fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// The full set of `--output` groups passed to a single xrandr invocation. Deserializes from
+/// JSON such as `{"outputs": [{"name": "HDMI-1-0", "mode": [2560, 1440], "primary": true}]}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct XrandrCommand {
+    pub outputs: Vec<OutputCommand>,
+}
+
+impl XrandrCommand {
+    pub fn command_string(&self) -> Vec<String> {
+        self.outputs
+            .iter()
+            .flat_map(OutputCommand::command_string)
+            .collect()
+    }
+    /// A hash of what the plan asks for that ignores the order of its outputs, the same across
+    /// runs and builds so it can be stored. FNV-1a over each output's arguments.
+    pub fn stable_hash(&self) -> u64 {
+        let mut outputs = self
+            .outputs
+            .iter()
+            .map(OutputCommand::command_string)
+            .collect::<Vec<Vec<String>>>();
+        outputs.sort();
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for arg in outputs.iter().flatten() {
+            // The trailing zero keeps `ab`,`c` apart from `a`,`bc`.
+            for byte in arg.bytes().chain([0]) {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+    /// `command_string`, refusing to hand xrandr anything `check_command` rejects.
+    pub fn checked_command_string(&self) -> Result<Vec<String>> {
+        let args = self.command_string();
+        check_command(&args)?;
+        Ok(args)
+    }
+    /// The same plan as `wlr-randr` arguments. Wayland has no primary output and wlr-randr
+    /// can't mirror or set properties, so primaries are dropped and the others are an error.
+    pub fn wlr_command_string(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        for output in &self.outputs {
+            args.push("--output".to_string());
+            args.push(output.name.clone());
+            let Some((width, height)) = output.mode else {
+                args.push("--off".to_string());
+                continue;
+            };
+            if let Some(source) = &output.same_as {
+                bail!("wlr-randr can't mirror {} onto {}", source, output.name);
+            }
+            if !output.properties.is_empty() {
+                bail!("wlr-randr can't set properties of {}", output.name);
+            }
+            args.push("--on".to_string());
+            if output.auto {
+                args.push("--preferred".to_string());
+            } else {
+                args.push("--mode".to_string());
+                args.push(match output.rate {
+                    Some(rate) => format!("{}x{}@{:.3}Hz", width, height, rate),
+                    None => Resolution::new(width, height).to_string(),
+                });
+            }
+            if let Some((x, y)) = output.position {
+                args.push("--pos".to_string());
+                args.push(format!("{},{}", x, y));
+            }
+            if let Some(scale) = output.scale {
+                args.push("--scale".to_string());
+                args.push(scale.to_string());
+            }
+            if let Some(rotation) = output.rotate {
+                args.push("--transform".to_string());
+                args.push(
+                    match rotation {
+                        Rotation::Normal => "normal",
+                        Rotation::Left => "90",
+                        Rotation::Inverted => "180",
+                        Rotation::Right => "270",
+                    }
+                    .to_string(),
+                );
+            }
+        }
+        Ok(args)
+    }
+    /// The whole xrandr invocation as one line that can be pasted into a shell.
+    pub fn shell_command(&self) -> String {
+        std::iter::once("xrandr".to_string())
+            .chain(self.command_string())
+            .map(|arg| shell_quote(&arg))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+    /// Groups the outputs being enabled and those being turned off, keeping the plan's order
+    /// within each group.
+    pub fn order_outputs(&mut self, order: ApplyOrder) {
+        match order {
+            ApplyOrder::OnFirst => self.outputs.sort_by_key(|o| o.mode.is_none()),
+            ApplyOrder::OffFirst => self.outputs.sort_by_key(|o| o.mode.is_some()),
+        }
+    }
+    /// Keeps the first enabled primary, or makes the largest enabled output primary if none is.
+    pub fn ensure_single_primary(&mut self) {
+        let primary = self
+            .outputs
+            .iter()
+            .position(|o| o.primary && o.mode.is_some())
+            .or_else(|| {
+                self.outputs
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, o)| {
+                        o.mode
+                            .map(|(width, height)| (i, width.saturating_mul(height)))
+                    })
+                    .reduce(|biggest, next| if next.1 > biggest.1 { next } else { biggest })
+                    .map(|(i, _)| i)
+            });
+        for (i, output) in self.outputs.iter_mut().enumerate() {
+            output.primary = Some(i) == primary;
+        }
+    }
+    /// xrandr refuses layouts spanning more than the screen's maximum framebuffer.
+    pub fn exceeds_screen(&self, screen: &ScreenInfo) -> Option<String> {
+        let (width, height) = self
+            .outputs
+            .iter()
+            .filter_map(|o| {
+                let (width, height) = o.footprint()?;
+                let (x, y) = o.position.unwrap_or((0, 0));
+                Some((x.max(0) as usize + width, y.max(0) as usize + height))
+            })
+            .fold((0, 0), |(w, h), (width, height)| {
+                (w.max(width), h.max(height))
+            });
+        if width <= screen.maximum.0 && height <= screen.maximum.1 {
+            return None;
+        }
+        Some(format!(
+            "Warning: the layout needs a {}x{} screen but the maximum is {}x{}.",
+            width, height, screen.maximum.0, screen.maximum.1
+        ))
+    }
+    /// Some GPUs can only drive a few outputs at once and quietly leave the rest dark. Names
+    /// the enabled outputs past the first `max`, without turning any of them off.
+    pub fn exceeds_output_limit(&self, max: usize) -> Option<String> {
+        let excess = self
+            .outputs
+            .iter()
+            .filter(|o| o.mode.is_some())
+            .skip(max)
+            .map(|o| o.name.as_str())
+            .collect::<Vec<&str>>();
+        if excess.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "Warning: the layout enables more than {} outputs, {} may stay dark.",
+            max,
+            excess.join(", ")
+        ))
+    }
+    /// Rotates each named output, which has to be on in the plan.
+    pub fn set_rotations(&mut self, rotations: &[(String, Rotation)]) -> Result<()> {
+        for (name, rotation) in rotations {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|o| &o.name == name && o.mode.is_some())
+                .context(format!(
+                    "--rotate given for {} which is not being turned on",
+                    name
+                ))?;
+            output.rotate = Some(*rotation);
+        }
+        Ok(())
+    }
+    /// Scales the primary output by `scale`, the selected output of an automatic layout.
+    pub fn scale_primary(&mut self, scale: f32) -> Result<()> {
+        let output = self
+            .outputs
+            .iter_mut()
+            .find(|o| o.primary && o.mode.is_some())
+            .context("--scale needs a primary output being turned on")?;
+        output.scale = Some(scale);
+        Ok(())
+    }
+    /// Places the enabled outputs left to right in plan order, each taking up its rotated size.
+    /// Outputs mirroring another stay where they are.
+    pub fn place_side_by_side(&mut self) {
+        let mut x = 0;
+        for output in &mut self.outputs {
+            if output.same_as.is_some() {
+                continue;
+            }
+            let Some((width, _)) = output.footprint() else {
+                continue;
+            };
+            output.position = Some((x, 0));
+            x += width as i32;
+        }
+    }
+    /// Rotates every enabled laptop panel, e.g. to follow a convertible's accelerometer.
+    pub fn rotate_internal(&mut self, rotation: Rotation, internal_prefixes: &[String]) {
+        for output in &mut self.outputs {
+            if output.mode.is_some() && is_internal(&output.name, internal_prefixes) {
+                output.rotate = Some(rotation);
+            }
+        }
+    }
+    /// Rotates enabled outputs whose EDID says they are portrait panels, outputs already being
+    /// rotated are left alone.
+    pub fn rotate_from_edid(&mut self, edids: &HashMap<String, Vec<u8>>) {
+        for output in &mut self.outputs {
+            if output.mode.is_none() || output.rotate.is_some() {
+                continue;
+            }
+            output.rotate = edids
+                .get(&output.name)
+                .and_then(|edid| edid::rotation_hint(edid));
+        }
+    }
+    /// `--listactivemonitors` doesn't report rotation, so it is compared against `-q` instead.
+    pub fn changes_rotation(&self, possible: &Monitors) -> bool {
+        self.outputs.iter().any(|output| {
+            output.rotate.is_some_and(|rotation| {
+                possible
+                    .find(&output.name)
+                    .is_some_and(|m| m.rotation != rotation)
+            })
+        })
+    }
+    /// Enables each named output at its given mode, whatever the plan had for it.
+    pub fn override_modes(&mut self, modes: &[(String, (usize, usize))]) -> Result<()> {
+        for (name, mode) in modes {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|o| &o.name == name)
+                .context(format!("--mode given for {} which is not connected", name))?;
+            output.mode = Some(*mode);
+            output.rate = None;
+        }
+        Ok(())
+    }
+    /// Turns the named outputs off whatever the plan had for them. When one of them was primary
+    /// the largest output left on takes over.
+    pub fn turn_off(&mut self, names: &[String]) {
+        let mut was_primary = false;
+        for output in self.outputs.iter_mut().filter(|o| names.contains(&o.name)) {
+            was_primary |= output.primary;
+            output.mode = None;
+            output.primary = false;
+        }
+        if was_primary {
+            self.ensure_single_primary();
+        }
+    }
+    /// Sets the panning area of each named output, which has to be on in the plan.
+    pub fn set_panning(&mut self, panning: &[(String, Panning)]) -> Result<()> {
+        for (name, area) in panning {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|o| &o.name == name && o.mode.is_some())
+                .context(format!(
+                    "--panning given for {} which is not being turned on",
+                    name
+                ))?;
+            output.panning = Some(*area);
+        }
+        Ok(())
+    }
+    /// Adds a `--set` of each named output's property, the output has to be on in the plan.
+    pub fn set_properties(&mut self, properties: &[(String, (String, String))]) -> Result<()> {
+        for (name, property) in properties {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|o| &o.name == name && o.mode.is_some())
+                .context(format!(
+                    "--set given for {} which is not being turned on",
+                    name
+                ))?;
+            output.properties.push(property.clone());
+        }
+        Ok(())
+    }
+    /// Shifts every positioned output so the layout's top left corner is at 0,0, some
+    /// compositors misbehave with negative or offset origins.
+    pub fn normalize_positions(&mut self) {
+        let positions = self.outputs.iter().filter_map(|o| o.position);
+        let Some(min_x) = positions.clone().map(|(x, _)| x).min() else {
+            return;
+        };
+        let min_y = positions.map(|(_, y)| y).min().unwrap_or(0);
+        for output in &mut self.outputs {
+            if let Some((x, y)) = &mut output.position {
+                *x -= min_x;
+                *y -= min_y;
+            }
+        }
+    }
+    /// Leaves the refresh rate of every output to xrandr.
+    pub fn clear_rates(&mut self) {
+        for output in &mut self.outputs {
+            output.rate = None;
+        }
+    }
+    /// Hands the choice of mode for every enabled output to xrandr's `--auto`.
+    pub fn use_auto_mode(&mut self) {
+        for output in self.outputs.iter_mut().filter(|o| o.mode.is_some()) {
+            output.auto = true;
+        }
+    }
+    /// Stops asserting the primary when `-q` already reports that output as the only primary,
+    /// so the window manager isn't told about a change that didn't happen. With several
+    /// marked primary the primary is asserted to clear the others.
+    pub fn skip_unchanged_primary(&mut self, possible: &Monitors) {
+        if possible.monitors.iter().filter(|m| m.primary).count() != 1 {
+            return;
+        }
+        for output in self.outputs.iter_mut().filter(|o| o.primary) {
+            if possible.find(&output.name).is_some_and(|m| m.primary) {
+                output.primary = false;
+            }
+        }
+    }
+    /// For setups where no output should be designated primary at all.
+    pub fn clear_primary(&mut self) {
+        for output in &mut self.outputs {
+            output.primary = false;
+        }
+    }
+    /// Whether applying this plan would change the active monitors reported by xrandr. Panning
+    /// and properties aren't reported at all, so a plan setting them always needs applying.
+    pub fn needs_change(&self, current: &Monitors) -> bool {
+        self.outputs
+            .iter()
+            .any(|o| o.panning.is_some() || !o.properties.is_empty())
+            || !self.matches(current)
+    }
+    /// Whether the `current` active monitors are exactly what this plan asks for. A plan
+    /// without a primary leaves the current primary alone, so it isn't compared. An output
+    /// mirroring another only matches when it already sits at the same spot.
+    pub fn matches(&self, current: &Monitors) -> bool {
+        let sets_primary = self.outputs.iter().any(|o| o.primary);
+        let enabled = self
+            .outputs
+            .iter()
+            .filter(|o| o.mode.is_some())
+            .collect::<Vec<&OutputCommand>>();
+        enabled.len() == current.monitors.len()
+            && enabled.iter().all(|output| {
+                current.monitors.iter().any(|m| {
+                    m.name == output.name
+                        && output.shows_as(m.resolution.width, m.resolution.height)
+                        && output
+                            .position
+                            .is_none_or(|position| m.position == Some(position))
+                        && output.same_as.as_ref().is_none_or(|source| {
+                            current
+                                .find(source)
+                                .is_some_and(|source| source.position == m.position)
+                        })
+                        && (!sets_primary || m.primary == output.primary)
+                })
+            })
+    }
+}
+
+/// Fails when an `--output` group would turn the output on without a `--mode` or `--auto`,
+/// leaving xrandr to pick something undefined. A zero sized mode counts as missing.
+pub fn check_command(args: &[String]) -> Result<()> {
+    let mut groups = Vec::new();
+    for arg in args {
+        if arg == "--output" {
+            groups.push(Vec::new());
+        } else if let Some(group) = groups.last_mut() {
+            group.push(arg.as_str());
+        }
+    }
+    for group in groups {
+        let Some((name, options)) = group.split_first() else {
+            bail!("Expected an output name after --output");
+        };
+        if options.contains(&"--off") || options.contains(&"--auto") {
+            continue;
+        }
+        let mode = options
+            .iter()
+            .position(|&option| option == "--mode")
+            .and_then(|i| options.get(i + 1))
+            .context(format!("{} would be turned on without a --mode", name))?;
+        if parse_mode(mode).is_ok_and(|(width, height)| width == 0 || height == 0) {
+            bail!("{} would be turned on at the empty mode {}", name, mode);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `WxH` mode string.
+pub fn parse_mode(mode: &str) -> Result<(usize, usize)> {
+    Ok(mode.parse::<Resolution>()?.into())
+}
+
+/// Settings for one output, a `None` mode turns the output off.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputCommand {
+    pub name: String,
+    #[serde(default)]
+    pub mode: Option<(usize, usize)>,
+    /// Left to xrandr when `None`.
+    #[serde(default)]
+    pub rate: Option<f32>,
+    /// Left to xrandr when `None`.
+    #[serde(default)]
+    pub position: Option<(i32, i32)>,
+    /// Output to mirror instead of being placed at a position.
+    #[serde(default)]
+    pub same_as: Option<String>,
+    /// Left as it is when `None`.
+    #[serde(default)]
+    pub rotate: Option<Rotation>,
+    #[serde(default)]
+    pub panning: Option<Panning>,
+    /// Factor passed to `--scale`, the output then takes up its mode times this.
+    #[serde(default)]
+    pub scale: Option<f32>,
+    /// Output properties such as `("Broadcast RGB", "Full")`, passed on with `--set`.
+    #[serde(default)]
+    pub properties: Vec<(String, String)>,
+    /// Let xrandr pick the mode with `--auto`, `mode` then only stands for the expected size.
+    #[serde(default)]
+    pub auto: bool,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+impl OutputCommand {
+    /// Whether an active output of this size matches the mode, `--listactivemonitors` reports
+    /// the rotated size for outputs turned on their side.
+    pub fn shows_as(&self, width: usize, height: usize) -> bool {
+        let Some(mode) = self.scaled_mode() else {
+            return false;
+        };
+        match self.rotate {
+            Some(rotation) if rotation.is_sideways() => (height, width) == mode,
+            Some(_) => (width, height) == mode,
+            None => (width, height) == mode || (height, width) == mode,
+        }
+    }
+    /// The width and height the output takes up in the layout once rotated, `None` when off.
+    pub fn footprint(&self) -> Option<(usize, usize)> {
+        let (width, height) = self.scaled_mode()?;
+        Some(match self.rotate {
+            Some(rotation) if rotation.is_sideways() => (height, width),
+            _ => (width, height),
+        })
+    }
+    /// The mode's size once `--scale` is applied, which is what xrandr then reports as active.
+    fn scaled_mode(&self) -> Option<(usize, usize)> {
+        let (width, height) = self.mode?;
+        Some(match self.scale {
+            Some(scale) => (
+                (width as f32 * scale).round() as usize,
+                (height as f32 * scale).round() as usize,
+            ),
+            None => (width, height),
+        })
+    }
+    pub fn command_string(&self) -> Vec<String> {
+        let mut args = vec!["--output".into(), self.name.clone()];
+        match self.mode {
+            Some((width, height)) => {
+                if self.auto {
+                    args.push("--auto".into());
+                } else {
+                    args.push("--mode".into());
+                    args.push(Resolution::new(width, height).to_string());
+                    if let Some(rate) = self.rate {
+                        args.push("--rate".into());
+                        args.push(format!("{:.2}", rate));
+                    }
+                }
+                if let Some((x, y)) = self.position {
+                    args.push("--pos".into());
+                    args.push(format!("{}x{}", x, y));
+                }
+                if let Some(source) = &self.same_as {
+                    args.push("--same-as".into());
+                    args.push(source.clone());
+                }
+                if let Some(rotation) = self.rotate {
+                    args.push("--rotate".into());
+                    args.push(rotation.to_string());
+                }
+                if let Some(panning) = self.panning {
+                    args.push("--panning".into());
+                    args.push(panning.to_string());
+                }
+                if let Some(scale) = self.scale {
+                    args.push("--scale".into());
+                    args.push(format!("{}x{}", scale, scale));
+                }
+                for (property, value) in &self.properties {
+                    args.push("--set".into());
+                    args.push(property.clone());
+                    args.push(value.clone());
+                }
+                if self.primary {
+                    args.push("--primary".into());
+                }
+            }
+            None => args.push("--off".into()),
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_command() {
+        assert!(check_command(&args(&[
+            "--output",
+            "eDP-1",
+            "--off",
+            "--output",
+            "HDMI-1-0",
+            "--mode",
+            "2560x1440",
+            "--primary",
+            "--output",
+            "DP-1",
+            "--auto",
+        ]))
+        .is_ok());
+        let error = check_command(&args(&["--output", "HDMI-1-0", "--primary"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "HDMI-1-0 would be turned on without a --mode"
+        );
+        assert!(check_command(&args(&["--output", "HDMI-1-0", "--mode", "0x1440"])).is_err());
+        assert!(check_command(&args(&["--output"])).is_err());
+    }
+
+    #[test]
+    fn test_use_auto_mode() {
+        let mut plan: XrandrCommand = serde_json::from_str(
+            r#"{"outputs": [
+                {"name": "eDP-1"},
+                {"name": "HDMI-1-0", "mode": [2560, 1440], "rate": 59.95, "primary": true}
+            ]}"#,
+        )
+        .unwrap();
+        plan.use_auto_mode();
+        assert_eq!(
+            plan.command_string(),
+            args(&[
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--auto",
+                "--primary"
+            ])
+        );
+        assert!(plan.checked_command_string().is_ok());
+        assert_eq!(
+            plan.wlr_command_string().unwrap()[3..],
+            args(&["--output", "HDMI-1-0", "--on", "--preferred"])
+        );
+        let plan: XrandrCommand =
+            serde_json::from_str(r#"{"outputs": [{"name": "DP-1", "mode": [0, 0]}]}"#).unwrap();
+        assert!(plan.checked_command_string().is_err());
+    }
+
+    #[test]
+    fn test_exceeds_output_limit() {
+        let plan: XrandrCommand = serde_json::from_str(
+            r#"{"outputs": [
+                {"name": "eDP-1", "mode": [1920, 1200]},
+                {"name": "DP-1"},
+                {"name": "HDMI-1-0", "mode": [2560, 1440], "primary": true},
+                {"name": "DP-2", "mode": [1920, 1080]}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(plan.exceeds_output_limit(3).is_none());
+        assert_eq!(
+            plan.exceeds_output_limit(1).unwrap(),
+            "Warning: the layout enables more than 1 outputs, HDMI-1-0, DP-2 may stay dark."
+        );
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        let plan = |outputs: &str| -> XrandrCommand {
+            serde_json::from_str(&format!(r#"{{"outputs": [{}]}}"#, outputs)).unwrap()
+        };
+        let edp = r#"{"name": "eDP-1", "mode": [1920, 1200], "position": [2560, 0]}"#;
+        let hdmi = r#"{"name": "HDMI-1-0", "mode": [2560, 1440], "primary": true}"#;
+        let forwards = plan(&format!("{}, {}", edp, hdmi));
+        let backwards = plan(&format!("{}, {}", hdmi, edp));
+        assert_eq!(forwards.stable_hash(), backwards.stable_hash());
+        let off = plan(&format!(r#"{{"name": "eDP-1"}}, {}"#, hdmi));
+        assert_ne!(forwards.stable_hash(), off.stable_hash());
+    }
+
+    #[test]
+    fn test_normalize_positions() {
+        let mut plan: XrandrCommand = serde_json::from_str(
+            r#"{"outputs": [
+                {"name": "eDP-1", "mode": [1920, 1200], "position": [-1920, -120]},
+                {"name": "HDMI-1-0", "mode": [2560, 1440], "position": [0, -360]},
+                {"name": "DP-1"}
+            ]}"#,
+        )
+        .unwrap();
+        plan.normalize_positions();
+        let positions = plan
+            .outputs
+            .iter()
+            .map(|o| o.position)
+            .collect::<Vec<Option<(i32, i32)>>>();
+        assert_eq!(positions, vec![Some((0, 240)), Some((1920, 0)), None]);
+    }
+}
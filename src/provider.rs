@@ -0,0 +1,101 @@
+use anyhow::{bail, Context, Result};
+
+/// One GPU from `xrandr --listproviders`.
+#[derive(Debug, PartialEq)]
+pub struct Provider {
+    pub name: String,
+    /// How many outputs it drives. xrandr lists outputs grouped by provider in this order, so
+    /// the counts are enough to tell which output belongs to which provider.
+    pub outputs: usize,
+}
+
+impl Provider {
+    /// Parses every `Provider N: id: ... outputs: 5 ... name:NVIDIA-G0` line.
+    pub fn parse_list(listproviders: &str) -> Result<Vec<Provider>> {
+        listproviders
+            .lines()
+            .filter(|line| line.trim_start().starts_with("Provider "))
+            .map(Provider::parse)
+            .collect()
+    }
+    fn parse(line: &str) -> Result<Provider> {
+        let name = line
+            .split_once("name:")
+            .context(format!("Expect provider line to contain a name: {}", line))?
+            .1
+            .trim();
+        let outputs = line
+            .split_once("outputs:")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .context(format!("Expect provider line to contain outputs: {}", line))?
+            .parse()
+            .context(format!(
+                "Provider output count should be an integer: {}",
+                line
+            ))?;
+        Ok(Provider {
+            name: name.to_string(),
+            outputs,
+        })
+    }
+    /// Names of the outputs driven by the provider `name`, taken from every output listed by
+    /// `-q` in order.
+    pub fn outputs_of<'a>(
+        providers: &[Provider],
+        name: &str,
+        output_names: &'a [String],
+    ) -> Result<&'a [String]> {
+        let mut start = 0;
+        for provider in providers {
+            let end = (start + provider.outputs).min(output_names.len());
+            if provider.name == name {
+                return Ok(&output_names[start..end]);
+            }
+            start = end;
+        }
+        let known = providers
+            .iter()
+            .map(|provider| provider.name.as_str())
+            .collect::<Vec<&str>>();
+        bail!("No provider named {}, found {}", name, known.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROVIDERS: &str = "Providers: number : 2
+Provider 0: id: 0x47 cap: 0xf, Source Output, Sink Output, Source Offload, Sink Offload crtcs: 3 outputs: 8 associated providers: 1 name:modesetting
+Provider 1: id: 0x1f8 cap: 0x2, Sink Output crtcs: 4 outputs: 5 associated providers: 1 name:NVIDIA-G0";
+
+    #[test]
+    fn test_parse_providers() {
+        let providers = Provider::parse_list(PROVIDERS).unwrap();
+        assert_eq!(
+            providers,
+            vec![
+                Provider {
+                    name: "modesetting".to_string(),
+                    outputs: 8
+                },
+                Provider {
+                    name: "NVIDIA-G0".to_string(),
+                    outputs: 5
+                }
+            ]
+        );
+        let names = [
+            "eDP-1", "DP-1", "HDMI-1", "DP-2", "HDMI-2", "DP-3", "HDMI-3", "HDMI-4",
+        ]
+        .iter()
+        .chain(&["DP-1-0", "DP-1-1", "DP-1-2", "DP-1-3", "HDMI-1-0"])
+        .map(|name| name.to_string())
+        .collect::<Vec<String>>();
+        assert_eq!(
+            Provider::outputs_of(&providers, "NVIDIA-G0", &names).unwrap(),
+            &names[8..]
+        );
+        assert!(Provider::outputs_of(&providers, "amdgpu", &names).is_err());
+    }
+}
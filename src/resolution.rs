@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// What can separate a width from a height, some captures print the multiplication sign `×`.
+pub const SEPARATORS: [char; 2] = ['x', '×'];
+
+/// A size in pixels, written `WxH`. Ordered by area, the wider of two equal areas is greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Resolution {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Resolution {
+    pub fn new(width: usize, height: usize) -> Resolution {
+        Resolution { width, height }
+    }
+    /// Saturates rather than overflowing on nonsense sizes.
+    pub fn area(self) -> usize {
+        self.width.saturating_mul(self.height)
+    }
+}
+
+impl Ord for Resolution {
+    fn cmp(&self, other: &Resolution) -> Ordering {
+        (self.area(), self.width, self.height).cmp(&(other.area(), other.width, other.height))
+    }
+}
+
+impl PartialOrd for Resolution {
+    fn partial_cmp(&self, other: &Resolution) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Resolution> {
+        let (width, height) = mode
+            .split_once(SEPARATORS)
+            .context(format!("Expect mode to be widthxheight: {}", mode))?;
+        Ok(Resolution {
+            width: width
+                .parse()
+                .context(format!("Mode width should be an integer: {}", mode))?,
+            height: height
+                .parse()
+                .context(format!("Mode height should be an integer: {}", mode))?,
+        })
+    }
+}
+
+impl From<(usize, usize)> for Resolution {
+    fn from((width, height): (usize, usize)) -> Resolution {
+        Resolution { width, height }
+    }
+}
+
+impl From<Resolution> for (usize, usize) {
+    fn from(resolution: Resolution) -> (usize, usize) {
+        (resolution.width, resolution.height)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let resolution: Resolution = "2560x1440".parse().unwrap();
+        assert_eq!(resolution, Resolution::new(2560, 1440));
+        assert_eq!(resolution.to_string(), "2560x1440");
+        assert_eq!(<(usize, usize)>::from(resolution), (2560, 1440));
+        assert!("2560".parse::<Resolution>().is_err());
+        assert!("2560xtall".parse::<Resolution>().is_err());
+        assert!("-1x1440".parse::<Resolution>().is_err());
+        assert_eq!(
+            "1920×1080".parse::<Resolution>().unwrap(),
+            Resolution::new(1920, 1080)
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut resolutions = vec![
+            Resolution::new(2560, 1440),
+            Resolution::new(1200, 1920),
+            Resolution::new(1920, 1200),
+            Resolution::new(3840, 2160),
+        ];
+        resolutions.sort();
+        assert_eq!(
+            resolutions,
+            vec![
+                Resolution::new(1200, 1920),
+                Resolution::new(1920, 1200),
+                Resolution::new(2560, 1440),
+                Resolution::new(3840, 2160),
+            ]
+        );
+        assert_eq!(
+            Resolution::new(usize::MAX, 2).area(),
+            Resolution::new(2, usize::MAX).area()
+        );
+        assert!(Resolution::new(usize::MAX, 2) > Resolution::new(2, usize::MAX));
+    }
+}
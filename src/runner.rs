@@ -0,0 +1,39 @@
+use crate::RandrError;
+use anyhow::{bail, Context, Result};
+use std::process::Command;
+
+/// Everything that reaches outside the process goes through a runner so tests can fake it.
+pub trait Runner {
+    /// Runs xrandr with `args`, returning its stdout.
+    fn xrandr(&mut self, args: &[String]) -> Result<String>;
+    /// Runs a user supplied command with inherited stdio, returning its exit code.
+    fn command(&mut self, command: &[String]) -> Result<i32>;
+}
+
+pub struct XrandrRunner;
+
+impl Runner for XrandrRunner {
+    fn xrandr(&mut self, args: &[String]) -> Result<String> {
+        let output = Command::new("xrandr")
+            .args(args)
+            .output()
+            .map_err(RandrError::XrandrSpawn)?;
+        if !output.status.success() {
+            bail!(
+                "xrandr {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8(output.stdout).map_err(|_| RandrError::NonUtf8Output)?)
+    }
+    fn command(&mut self, command: &[String]) -> Result<i32> {
+        let (program, args) = command.split_first().context("Expected a command to run")?;
+        let status = Command::new(program)
+            .args(args)
+            .status()
+            .context(format!("Failed to run {}", program))?;
+        // A command killed by a signal has no exit code, report it as a plain failure.
+        Ok(status.code().unwrap_or(1))
+    }
+}
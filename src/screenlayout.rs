@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+
+/// The arguments of the xrandr call in a `~/.screenlayout` script as arandr and autorandr
+/// write them, e.g. `#!/bin/sh` followed by `xrandr --output eDP-1 --mode 1920x1080 ...`.
+/// Comments and backslash continued lines are understood, anything else shell-like isn't.
+pub fn parse_script(script: &str) -> Result<Vec<String>> {
+    let script = script.replace("\\\n", " ");
+    let line = script
+        .lines()
+        .map(str::trim)
+        .find(|line| line.split_whitespace().next() == Some("xrandr"))
+        .context("Expected the script to run xrandr")?;
+    let mut words = split_words(line)?;
+    words.remove(0);
+    Ok(words)
+}
+
+/// The outputs the xrandr `args` leave on, every `--output` without an `--off`.
+pub fn enabled_outputs(args: &[String]) -> Vec<String> {
+    let mut outputs: Vec<(String, bool)> = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => outputs.extend(args.next().map(|name| (name.clone(), true))),
+            "--off" => {
+                if let Some((_, on)) = outputs.last_mut() {
+                    *on = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    outputs
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+        .collect()
+}
+
+/// Splits a shell command line on whitespace, honouring single and double quotes and
+/// stopping at a comment.
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(quoted) => word.push(quoted),
+                        None => bail!("Unterminated quote in {}", line),
+                    }
+                }
+            }
+            '#' if word.is_none() => break,
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = r#"#!/bin/sh
+# Generated by arandr
+xrandr --output eDP-1 --off \
+    --output HDMI-1-0 --primary --mode 2560x1440 --pos 0x0 --rotate normal --output 'DP 1' --off # docked
+"#;
+        assert_eq!(
+            parse_script(script).unwrap(),
+            vec![
+                "--output",
+                "eDP-1",
+                "--off",
+                "--output",
+                "HDMI-1-0",
+                "--primary",
+                "--mode",
+                "2560x1440",
+                "--pos",
+                "0x0",
+                "--rotate",
+                "normal",
+                "--output",
+                "DP 1",
+                "--off",
+            ]
+        );
+        assert_eq!(
+            enabled_outputs(&parse_script(script).unwrap()),
+            vec!["HDMI-1-0"]
+        );
+        assert!(parse_script("#!/bin/sh\nautorandr --change\n").is_err());
+        assert!(parse_script("xrandr --output 'eDP-1").is_err());
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Records SIGTERM or SIGINT instead of dying on them, so long running modes can put the
+/// displays back before exiting. Clones share the same flag.
+#[derive(Clone, Default)]
+pub struct Terminate {
+    requested: Arc<AtomicBool>,
+}
+
+impl Terminate {
+    pub fn install() -> Result<Terminate> {
+        let requested = Arc::new(AtomicBool::new(false));
+        for signal in [SIGTERM, SIGINT] {
+            signal_hook::flag::register(signal, Arc::clone(&requested))
+                .context(format!("Failed to install a handler for signal {}", signal))?;
+        }
+        Ok(Terminate { requested })
+    }
+    /// Asks for the same stop a signal would.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+}
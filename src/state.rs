@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// How far `--cycle-resolution` got, kept between invocations as `NAME INDEX`.
+#[derive(Debug, PartialEq)]
+pub struct CycleState {
+    pub output: String,
+    /// Position in the output's mode list, as xrandr orders it.
+    pub index: usize,
+}
+
+impl CycleState {
+    /// `$XDG_STATE_HOME/not-shit-randr/cycle`, falling back to `~/.local/state`.
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+            })?;
+        Some(state_home.join("not-shit-randr").join("cycle"))
+    }
+    /// A missing file just means nothing has been cycled yet.
+    pub fn load(path: &Path) -> Result<Option<CycleState>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(format!("Failed to read state {}", path.display())),
+        };
+        let (output, index) = text.trim().split_once(' ').context(format!(
+            "Expect cycle state to be NAME INDEX: {}",
+            text.trim()
+        ))?;
+        Ok(Some(CycleState {
+            output: output.to_string(),
+            index: index
+                .parse()
+                .context(format!("Cycle index should be an integer: {}", index))?,
+        }))
+    }
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!(
+                "Failed to create state directory {}",
+                dir.display()
+            ))?;
+        }
+        std::fs::write(path, format!("{} {}\n", self.output, self.index))
+            .context(format!("Failed to write state {}", path.display()))
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Something besides the connected outputs that `--watch` chooses the layout again for.
+pub trait ChangeSource {
+    /// Whether it changed since the last call.
+    fn changed(&mut self) -> bool;
+}
+
+/// A file, such as the config, noticed changing by its modification time. A file appearing
+/// or disappearing counts as a change too.
+pub struct ModifiedTime {
+    path: PathBuf,
+    last: Option<SystemTime>,
+}
+
+impl ModifiedTime {
+    pub fn new(path: PathBuf) -> ModifiedTime {
+        let last = modified(&path);
+        ModifiedTime { path, last }
+    }
+}
+
+impl ChangeSource for ModifiedTime {
+    fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        let changed = now != self.last;
+        self.last = now;
+        changed
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Waits out flapping connectors, a single plug can report the outputs changing a few times
+/// before they settle.
+#[derive(Debug)]
+pub struct Debounce {
+    delay: Duration,
+    /// Signature of the connected outputs last acted on.
+    settled: String,
+    /// A different signature and when it was first seen.
+    pending: Option<(String, Instant)>,
+}
+
+impl Debounce {
+    pub fn new(settled: String, delay: Duration) -> Debounce {
+        Debounce {
+            delay,
+            settled,
+            pending: None,
+        }
+    }
+    /// Records the `signature` seen at `now`. Returns true once a change has held for the whole
+    /// delay, it then counts as settled.
+    pub fn observe(&mut self, signature: String, now: Instant) -> bool {
+        if signature == self.settled {
+            self.pending = None;
+            return false;
+        }
+        match &self.pending {
+            Some((pending, since)) if *pending == signature => {
+                if now.duration_since(*since) < self.delay {
+                    return false;
+                }
+                self.settled = signature;
+                self.pending = None;
+                true
+            }
+            _ => {
+                self.pending = Some((signature, now));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut debounce = Debounce::new("eDP-1".to_string(), 2 * second);
+        assert!(!debounce.observe("eDP-1".to_string(), start));
+        // Plugged in, then the connector flaps before settling.
+        assert!(!debounce.observe("HDMI-1-0,eDP-1".to_string(), start + second));
+        assert!(!debounce.observe("eDP-1".to_string(), start + 2 * second));
+        assert!(!debounce.observe("HDMI-1-0,eDP-1".to_string(), start + 3 * second));
+        assert!(!debounce.observe("HDMI-1-0,eDP-1".to_string(), start + 4 * second));
+        assert!(debounce.observe("HDMI-1-0,eDP-1".to_string(), start + 5 * second));
+        assert!(!debounce.observe("HDMI-1-0,eDP-1".to_string(), start + 9 * second));
+    }
+
+    #[test]
+    fn test_modified_time() {
+        let path =
+            std::env::temp_dir().join(format!("not-shit-randr-mtime-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut file = ModifiedTime::new(path.clone());
+        assert!(!file.changed());
+        std::fs::write(&path, "height = 1080").unwrap();
+        assert!(file.changed());
+        assert!(!file.changed());
+        std::fs::remove_file(&path).unwrap();
+        assert!(file.changed());
+    }
+}